@@ -0,0 +1,287 @@
+use super::constant_time_eq;
+use crate::error::Result;
+
+// ChaCha20-Poly1305 authenticated encryption as specified in RFC 8439.
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+// Encrypts `plaintext` and returns the ciphertext with the tag appended.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = plaintext.to_vec();
+    chacha20_xor(key, nonce, 1, &mut sealed);
+    let tag = tag(key, nonce, aad, &sealed);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+pub fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Result<Vec<u8>> {
+    if sealed.len() < TAG_LEN {
+        return Err("Ciphertext is too short".into());
+    }
+    let (ciphertext, received_tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    if !constant_time_eq(&tag(key, nonce, aad, ciphertext), received_tag) {
+        return Err("Ciphertext failed authentication".into());
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, nonce, 1, &mut plaintext);
+    Ok(plaintext)
+}
+
+fn tag(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, nonce, 0);
+    let mut poly_key = [0u8; 32];
+    poly_key.copy_from_slice(&block[..32]);
+
+    let mut mac_data = Vec::with_capacity(aad.len() + ciphertext.len() + 48);
+    mac_data.extend_from_slice(aad);
+    mac_data.resize(mac_data.len().div_ceil(16) * 16, 0);
+    mac_data.extend_from_slice(ciphertext);
+    mac_data.resize(mac_data.len().div_ceil(16) * 16, 0);
+    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly1305(&poly_key, &mac_data)
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn chacha20_block(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> [u8; 64] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        initial[4 + i] = le32(&key[i * 4..]);
+    }
+    initial[12] = counter;
+    for i in 0..3 {
+        initial[13 + i] = le32(&nonce[i * 4..]);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut output = [0u8; 64];
+    for (i, chunk) in output.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+    }
+    output
+}
+
+fn chacha20_xor(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let keystream = chacha20_block(key, nonce, counter.wrapping_add(i as u32));
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+// Poly1305 with 26-bit limbs, following poly1305-donna.
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_LEN] {
+    const MASK: u32 = 0x3ffffff;
+    let r0 = le32(&key[0..]) & 0x3ffffff;
+    let r1 = (le32(&key[3..]) >> 2) & 0x3ffff03;
+    let r2 = (le32(&key[6..]) >> 4) & 0x3ffc0ff;
+    let r3 = (le32(&key[9..]) >> 6) & 0x3f03fff;
+    let r4 = (le32(&key[12..]) >> 8) & 0x00fffff;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let (mut h0, mut h1, mut h2, mut h3, mut h4) = (0u32, 0u32, 0u32, 0u32, 0u32);
+
+    for chunk in message.chunks(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let high_bit = if chunk.len() == 16 {
+            1 << 24
+        } else {
+            block[chunk.len()] = 1;
+            0
+        };
+
+        h0 += le32(&block[0..]) & MASK;
+        h1 += (le32(&block[3..]) >> 2) & MASK;
+        h2 += (le32(&block[6..]) >> 4) & MASK;
+        h3 += (le32(&block[9..]) >> 6) & MASK;
+        h4 += (le32(&block[12..]) >> 8) | high_bit;
+
+        let m = |a: u32, b: u32| u64::from(a) * u64::from(b);
+        let d0 = m(h0, r0) + m(h1, s4) + m(h2, s3) + m(h3, s2) + m(h4, s1);
+        let mut d1 = m(h0, r1) + m(h1, r0) + m(h2, s4) + m(h3, s3) + m(h4, s2);
+        let mut d2 = m(h0, r2) + m(h1, r1) + m(h2, r0) + m(h3, s4) + m(h4, s3);
+        let mut d3 = m(h0, r3) + m(h1, r2) + m(h2, r1) + m(h3, r0) + m(h4, s4);
+        let mut d4 = m(h0, r4) + m(h1, r3) + m(h2, r2) + m(h3, r1) + m(h4, r0);
+
+        let mut carry = (d0 >> 26) as u32;
+        h0 = d0 as u32 & MASK;
+        d1 += u64::from(carry);
+        carry = (d1 >> 26) as u32;
+        h1 = d1 as u32 & MASK;
+        d2 += u64::from(carry);
+        carry = (d2 >> 26) as u32;
+        h2 = d2 as u32 & MASK;
+        d3 += u64::from(carry);
+        carry = (d3 >> 26) as u32;
+        h3 = d3 as u32 & MASK;
+        d4 += u64::from(carry);
+        carry = (d4 >> 26) as u32;
+        h4 = d4 as u32 & MASK;
+        h0 += carry * 5;
+        carry = h0 >> 26;
+        h0 &= MASK;
+        h1 += carry;
+    }
+
+    let mut carry = h1 >> 26;
+    h1 &= MASK;
+    h2 += carry;
+    carry = h2 >> 26;
+    h2 &= MASK;
+    h3 += carry;
+    carry = h3 >> 26;
+    h3 &= MASK;
+    h4 += carry;
+    carry = h4 >> 26;
+    h4 &= MASK;
+    h0 += carry * 5;
+    carry = h0 >> 26;
+    h0 &= MASK;
+    h1 += carry;
+
+    // Compute h - p and keep it if it didn't underflow, in constant time
+    let mut g0 = h0.wrapping_add(5);
+    carry = g0 >> 26;
+    g0 &= MASK;
+    let mut g1 = h1.wrapping_add(carry);
+    carry = g1 >> 26;
+    g1 &= MASK;
+    let mut g2 = h2.wrapping_add(carry);
+    carry = g2 >> 26;
+    g2 &= MASK;
+    let mut g3 = h3.wrapping_add(carry);
+    carry = g3 >> 26;
+    g3 &= MASK;
+    let g4 = h4.wrapping_add(carry).wrapping_sub(1 << 26);
+
+    let select_g = (g4 >> 31).wrapping_sub(1);
+    let select_h = !select_g;
+    h0 = (h0 & select_h) | (g0 & select_g);
+    h1 = (h1 & select_h) | (g1 & select_g);
+    h2 = (h2 & select_h) | (g2 & select_g);
+    h3 = (h3 & select_h) | (g3 & select_g);
+    h4 = (h4 & select_h) | (g4 & select_g);
+
+    let words = [
+        h0 | (h1 << 26),
+        (h1 >> 6) | (h2 << 20),
+        (h2 >> 12) | (h3 << 14),
+        (h3 >> 18) | (h4 << 8),
+    ];
+
+    let mut tag = [0u8; TAG_LEN];
+    let mut sum = 0u64;
+    for (i, word) in words.iter().enumerate() {
+        sum = u64::from(*word) + u64::from(le32(&key[16 + i * 4..])) + (sum >> 32);
+        tag[i * 4..i * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+    }
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+
+    // RFC 8439, section 2.3.2
+    #[test]
+    fn chacha20_block_function() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| i as u8);
+        let nonce: [u8; NONCE_LEN] = from_hex("000000090000004a00000000").try_into().unwrap();
+        assert_eq!(
+            chacha20_block(&key, &nonce, 1).to_vec(),
+            from_hex(
+                "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e
+                 d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+            )
+        );
+    }
+
+    // RFC 8439, section 2.5.2
+    #[test]
+    fn poly1305_mac() {
+        let key: [u8; 32] =
+            from_hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b")
+                .try_into()
+                .unwrap();
+        assert_eq!(
+            poly1305(&key, b"Cryptographic Forum Research Group").to_vec(),
+            from_hex("a8061dc1305136c6c22b8baf0c0127a9")
+        );
+    }
+
+    // RFC 8439, section 2.8.2
+    #[test]
+    fn aead_seal_and_open() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: [u8; NONCE_LEN] = from_hex("070000004041424344454647").try_into().unwrap();
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let expected = from_hex(
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6
+             3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36
+             92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc
+             3ff4def08e4b7a9de576d26586cec64b6116
+             1ae10b594f09e26a7e902ecbd0600691",
+        );
+
+        let sealed = seal(&key, &nonce, &aad, SUNSCREEN);
+        assert_eq!(sealed, expected);
+        assert_eq!(open(&key, &nonce, &aad, &sealed).unwrap(), SUNSCREEN);
+    }
+
+    #[test]
+    fn open_rejects_tampering() {
+        let key = [7u8; KEY_LEN];
+        let nonce = [9u8; NONCE_LEN];
+        let sealed = seal(&key, &nonce, b"aad", SUNSCREEN);
+
+        let mut flipped = sealed.clone();
+        flipped[3] ^= 1;
+        assert!(open(&key, &nonce, b"aad", &flipped).is_err());
+        assert!(open(&key, &nonce, b"other aad", &sealed).is_err());
+        assert!(open(&key, &[0u8; NONCE_LEN], b"aad", &sealed).is_err());
+        assert!(open(&key, &nonce, b"aad", &sealed[..TAG_LEN - 1]).is_err());
+    }
+}
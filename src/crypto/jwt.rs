@@ -0,0 +1,387 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{constant_time_eq, hmac_sha256};
+use crate::error::Result;
+use crate::utils::{base64, Json};
+
+// JSON Web Tokens (RFC 7519) signed with HS256 only. The algorithm is fixed
+// rather than read from the token, so `alg: none` and key-confusion tricks
+// don't apply.
+
+const MIN_SECRET_LEN: usize = 32;
+
+// The registered claims we validate, plus roles for the `Principal`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Claims {
+    pub subject: String,
+    pub issuer: Option<String>,
+    pub audience: Vec<String>,
+    pub expires_at: u64,
+    pub not_before: Option<u64>,
+    pub issued_at: Option<u64>,
+    pub roles: Vec<String>,
+    // `amr` from RFC 8176, e.g. "pwd" and "otp" after a TOTP login.
+    pub auth_methods: Vec<String>,
+}
+
+impl Claims {
+    fn to_json(&self) -> Json {
+        let audience = match self.audience.as_slice() {
+            [] => Json::Null,
+            [single] => Json::from(single.as_str()),
+            many => Json::from(many.to_vec()),
+        };
+        let mut json = Json::object().field("sub", self.subject.as_str());
+        if let Some(issuer) = &self.issuer {
+            json = json.field("iss", issuer.as_str());
+        }
+        if audience != Json::Null {
+            json = json.field("aud", audience);
+        }
+        json = json.field("exp", self.expires_at);
+        if let Some(not_before) = self.not_before {
+            json = json.field("nbf", not_before);
+        }
+        if let Some(issued_at) = self.issued_at {
+            json = json.field("iat", issued_at);
+        }
+        json = json.field("roles", self.roles.clone());
+        if !self.auth_methods.is_empty() {
+            json = json.field("amr", self.auth_methods.clone());
+        }
+        json
+    }
+
+    fn from_json(json: &Json) -> Result<Claims> {
+        let string = |key: &str| json.get(key).and_then(Json::as_str).map(String::from);
+        let number = |key: &str| -> Result<Option<u64>> {
+            match json.get(key) {
+                None => Ok(None),
+                Some(value) => value
+                    .as_u64()
+                    .map(Some)
+                    .ok_or_else(|| format!("Invalid {} claim", key).into()),
+            }
+        };
+        let strings = |value: Option<&Json>| -> Vec<String> {
+            match value {
+                Some(Json::String(s)) => vec![s.clone()],
+                Some(Json::Array(items)) => items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(String::from))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        Ok(Claims {
+            subject: string("sub").ok_or("Token has no subject")?,
+            issuer: string("iss"),
+            audience: strings(json.get("aud")),
+            expires_at: number("exp")?.ok_or("Token has no expiry")?,
+            not_before: number("nbf")?,
+            issued_at: number("iat")?,
+            roles: strings(json.get("roles")),
+            auth_methods: strings(json.get("amr")),
+        })
+    }
+}
+
+struct JwtKey {
+    id: String,
+    secret: Vec<u8>,
+}
+
+// Issues and verifies tokens. The first key signs, the others are retired
+// keys still accepted, picked by the `kid` header, so secrets can rotate.
+pub struct Jwt {
+    keys: Vec<JwtKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: Duration,
+}
+
+impl Jwt {
+    pub fn new(key_id: impl ToString, secret: impl AsRef<[u8]>) -> Result<Jwt> {
+        Ok(Jwt {
+            keys: vec![jwt_key(key_id, secret)?],
+            issuer: None,
+            audience: None,
+            leeway: Duration::from_secs(60),
+        })
+    }
+
+    pub fn previous_key(mut self, key_id: impl ToString, secret: impl AsRef<[u8]>) -> Result<Jwt> {
+        self.keys.push(jwt_key(key_id, secret)?);
+        Ok(self)
+    }
+
+    // Put into issued tokens and required of verified ones.
+    pub fn issuer(mut self, issuer: impl ToString) -> Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    // Put into issued tokens and required of verified ones.
+    pub fn audience(mut self, audience: impl ToString) -> Self {
+        self.audience = Some(audience.to_string());
+        self
+    }
+
+    // Clock skew tolerated when checking `exp` and `nbf`.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    // Claims for `subject` valid from now for `lifetime`, with this issuer's
+    // `iss` and `aud`.
+    pub fn claims(&self, subject: impl ToString, roles: Vec<String>, lifetime: Duration) -> Claims {
+        let now = unix_time(SystemTime::now());
+        Claims {
+            subject: subject.to_string(),
+            issuer: self.issuer.clone(),
+            audience: self.audience.iter().cloned().collect(),
+            expires_at: now + lifetime.as_secs(),
+            not_before: Some(now),
+            issued_at: Some(now),
+            roles,
+            auth_methods: Vec::new(),
+        }
+    }
+
+    pub fn sign(&self, claims: &Claims) -> Result<String> {
+        let key = &self.keys[0];
+        let header = Json::object()
+            .field("alg", "HS256")
+            .field("typ", "JWT")
+            .field("kid", key.id.as_str());
+        let signing_input = format!(
+            "{}.{}",
+            base64::encode_url(header.to_string().as_bytes()),
+            base64::encode_url(claims.to_json().to_string().as_bytes())
+        );
+        let signature = hmac_sha256(&key.secret, signing_input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            base64::encode_url(&signature)
+        ))
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("Malformed token".into());
+        };
+
+        let header_json = Json::parse(&String::from_utf8(base64::decode_url(header)?)?)?;
+        if header_json.get("alg").and_then(Json::as_str) != Some("HS256") {
+            return Err("Unsupported token algorithm".into());
+        }
+        let key = match header_json.get("kid") {
+            Some(kid) => {
+                let kid = kid.as_str().ok_or("Invalid key id")?;
+                self.keys
+                    .iter()
+                    .find(|key| key.id == kid)
+                    .ok_or("Unknown key id")?
+            }
+            None => &self.keys[0],
+        };
+        let signing_input = &token[..header.len() + 1 + payload.len()];
+        let expected = hmac_sha256(&key.secret, signing_input.as_bytes());
+        if !constant_time_eq(&expected, &base64::decode_url(signature)?) {
+            return Err("Invalid token signature".into());
+        }
+
+        let claims = Claims::from_json(&Json::parse(&String::from_utf8(base64::decode_url(
+            payload,
+        )?)?)?)?;
+        let now = unix_time(SystemTime::now());
+        let leeway = self.leeway.as_secs();
+        if now >= claims.expires_at.saturating_add(leeway) {
+            return Err("Token has expired".into());
+        }
+        if claims
+            .not_before
+            .is_some_and(|not_before| now.saturating_add(leeway) < not_before)
+        {
+            return Err("Token is not valid yet".into());
+        }
+        if self.issuer.is_some() && claims.issuer != self.issuer {
+            return Err("Token has the wrong issuer".into());
+        }
+        if let Some(audience) = &self.audience {
+            if !claims.audience.contains(audience) {
+                return Err("Token is meant for another audience".into());
+            }
+        }
+        Ok(claims)
+    }
+}
+
+fn jwt_key(key_id: impl ToString, secret: impl AsRef<[u8]>) -> Result<JwtKey> {
+    let secret = secret.as_ref();
+    if secret.len() < MIN_SECRET_LEN {
+        return Err(format!("JWT secret must be at least {} bytes", MIN_SECRET_LEN).into());
+    }
+    Ok(JwtKey {
+        id: key_id.to_string(),
+        secret: secret.to_vec(),
+    })
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn roles() -> Vec<String> {
+        vec!["admin".to_string()]
+    }
+
+    // RFC 7515, appendix A.1. Its payload has no `sub`, so only the HS256
+    // signature is checked here.
+    #[test]
+    fn rfc7515_hs256_signature() {
+        let key = base64::decode_url(
+            "AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow",
+        )
+        .unwrap();
+        let signing_input = "eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9\
+            .eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+        assert_eq!(
+            base64::encode_url(&hmac_sha256(&key, signing_input.as_bytes())),
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let jwt = Jwt::new("k1", SECRET).unwrap().issuer("me").audience("you");
+        let mut claims = jwt.claims("alice", roles(), Duration::from_secs(60));
+        claims.auth_methods = vec!["pwd".to_string(), "otp".to_string()];
+        let token = jwt.sign(&claims).unwrap();
+        assert_eq!(jwt.verify(&token).unwrap(), claims);
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let jwt = Jwt::new("k1", SECRET).unwrap();
+        let token = jwt
+            .sign(&jwt.claims("alice", roles(), Duration::from_secs(60)))
+            .unwrap();
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+        let (header, _) = signing_input.split_once('.').unwrap();
+
+        let forged = jwt.claims("mallory", roles(), Duration::from_secs(60));
+        let payload = base64::encode_url(forged.to_json().to_string().as_bytes());
+        assert!(jwt
+            .verify(&format!("{}.{}.{}", header, payload, signature))
+            .is_err());
+
+        let other = Jwt::new("k1", b"a different secret of enough length").unwrap();
+        assert!(other.verify(&token).is_err());
+        assert!(jwt.verify(signing_input).is_err());
+    }
+
+    #[test]
+    fn rejects_other_algorithms() {
+        let jwt = Jwt::new("k1", SECRET).unwrap();
+        let claims = jwt.claims("alice", roles(), Duration::from_secs(60));
+        let payload = base64::encode_url(claims.to_json().to_string().as_bytes());
+        for alg in ["none", "HS512", "RS256"] {
+            let header = Json::object().field("alg", alg).field("kid", "k1");
+            let header = base64::encode_url(header.to_string().as_bytes());
+            // Validly signed, so only the algorithm can be the problem
+            let signing_input = format!("{}.{}", header, payload);
+            let signature = hmac_sha256(SECRET, signing_input.as_bytes());
+            let token = format!("{}.{}", signing_input, base64::encode_url(&signature));
+            assert!(jwt.verify(&token).is_err(), "{} was accepted", alg);
+        }
+    }
+
+    #[test]
+    fn checks_time_issuer_and_audience() {
+        let jwt = Jwt::new("k1", SECRET)
+            .unwrap()
+            .issuer("me")
+            .audience("you")
+            .leeway(Duration::from_secs(30));
+        let now = unix_time(SystemTime::now());
+        let valid = jwt.claims("alice", roles(), Duration::from_secs(60));
+        let verify = |claims: Claims| jwt.verify(&jwt.sign(&claims).unwrap());
+
+        assert!(verify(Claims {
+            expires_at: now - 10,
+            ..valid.clone()
+        })
+        .is_ok());
+        assert!(verify(Claims {
+            expires_at: now - 31,
+            ..valid.clone()
+        })
+        .is_err());
+        assert!(verify(Claims {
+            not_before: Some(now + 10),
+            ..valid.clone()
+        })
+        .is_ok());
+        assert!(verify(Claims {
+            not_before: Some(now + 31),
+            ..valid.clone()
+        })
+        .is_err());
+        assert!(verify(Claims {
+            issuer: Some("them".to_string()),
+            ..valid.clone()
+        })
+        .is_err());
+        assert!(verify(Claims {
+            issuer: None,
+            ..valid.clone()
+        })
+        .is_err());
+        assert!(verify(Claims {
+            audience: vec!["them".to_string()],
+            ..valid.clone()
+        })
+        .is_err());
+        assert!(verify(Claims {
+            audience: vec!["them".to_string(), "you".to_string()],
+            ..valid
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn picks_the_key_by_id() {
+        let old = Jwt::new("old", SECRET).unwrap();
+        let token = old
+            .sign(&old.claims("alice", roles(), Duration::from_secs(60)))
+            .unwrap();
+
+        let rotated = Jwt::new("new", b"the new secret, also 32 bytes long")
+            .unwrap()
+            .previous_key("old", SECRET)
+            .unwrap();
+        assert!(rotated.verify(&token).is_ok());
+
+        let forgotten = Jwt::new("new", b"the new secret, also 32 bytes long").unwrap();
+        assert!(forgotten.verify(&token).is_err());
+    }
+
+    #[test]
+    fn refuses_short_secrets() {
+        assert!(Jwt::new("k1", b"too short").is_err());
+    }
+}
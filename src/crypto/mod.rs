@@ -0,0 +1,35 @@
+pub mod chacha20poly1305;
+pub mod hmac;
+pub mod jwt;
+pub mod password;
+pub mod pbkdf2;
+pub mod sha1;
+pub mod sha256;
+pub mod totp;
+
+pub use hmac::*;
+pub use jwt::{Claims, Jwt};
+pub use password::*;
+pub use pbkdf2::*;
+pub use sha1::hmac_sha1;
+pub use sha256::Sha256;
+
+// Compares two byte strings without returning early on the first difference,
+// so the time taken doesn't reveal how much of a secret was guessed right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
+// Test vectors are written in hex, spaces allowed.
+#[cfg(test)]
+pub(crate) fn from_hex(hex: &str) -> Vec<u8> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
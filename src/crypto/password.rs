@@ -0,0 +1,130 @@
+use std::sync::OnceLock;
+
+use super::{constant_time_eq, pbkdf2_hmac_sha256};
+use crate::error::Result;
+use crate::utils::{base64, random};
+
+// Stored password hashes look like
+//
+//     $pbkdf2-sha256$i=100000$<salt>$<hash>
+//
+// with salt and hash in unpadded URL-safe base64. Recording the algorithm and
+// iteration count lets `needs_rehash` spot hashes made with older parameters.
+const ALGORITHM: &str = "pbkdf2-sha256";
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    fn parse(encoded: &str) -> Result<PasswordHash> {
+        let parts: Vec<&str> = encoded.split('$').collect();
+        let ["", algorithm, params, salt, hash] = parts[..] else {
+            return Err("Malformed password hash".into());
+        };
+        if algorithm != ALGORITHM {
+            return Err(format!("Unsupported password hash algorithm {}", algorithm).into());
+        }
+        let iterations = params
+            .strip_prefix("i=")
+            .and_then(|i| i.parse::<u32>().ok())
+            .filter(|&i| i > 0)
+            .ok_or("Invalid password hash iteration count")?;
+        let salt = base64::decode_url(salt)?;
+        let hash = base64::decode_url(hash)?;
+        // An empty hash would match any password
+        if salt.len() < SALT_LEN || hash.len() != HASH_LEN {
+            return Err("Invalid password hash salt or length".into());
+        }
+        Ok(PasswordHash {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    hash_password_with(password, DEFAULT_ITERATIONS)
+}
+
+pub fn hash_password_with(password: &str, iterations: u32) -> Result<String> {
+    if iterations == 0 {
+        return Err("Iteration count must be at least 1".into());
+    }
+    let salt = random::bytes(SALT_LEN)?;
+    let mut hash = [0u8; HASH_LEN];
+    pbkdf2_hmac_sha256(password.as_bytes(), &salt, iterations, &mut hash);
+    Ok(format!(
+        "${}$i={}${}${}",
+        ALGORITHM,
+        iterations,
+        base64::encode_url(&salt),
+        base64::encode_url(&hash)
+    ))
+}
+
+pub fn verify_password(password: &str, encoded: &str) -> Result<bool> {
+    let stored = PasswordHash::parse(encoded)?;
+    let mut hash = vec![0u8; stored.hash.len()];
+    pbkdf2_hmac_sha256(password.as_bytes(), &stored.salt, stored.iterations, &mut hash);
+    Ok(constant_time_eq(&hash, &stored.hash))
+}
+
+// Does the work of `verify_password` against a throwaway hash, for when the
+// user doesn't exist, so response times don't reveal which usernames do.
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("").unwrap_or_default());
+    let _ = verify_password(password, hash);
+}
+
+// True when the hash was made with weaker parameters than we use today, or
+// can't be read at all.
+pub fn needs_rehash(encoded: &str) -> bool {
+    match PasswordHash::parse(encoded) {
+        Ok(stored) => stored.iterations < DEFAULT_ITERATIONS,
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_its_own_hashes() {
+        let hash = hash_password_with("correct horse", 1).unwrap();
+        assert!(verify_password("correct horse", &hash).unwrap());
+        assert!(!verify_password("wrong horse", &hash).unwrap());
+        assert!(needs_rehash(&hash));
+    }
+
+    #[test]
+    fn rejects_truncated_hashes() {
+        let hash = hash_password_with("correct horse", 1).unwrap();
+        let (prefix, full_hash) = hash.rsplit_once('$').unwrap();
+        let (params, salt) = prefix.rsplit_once('$').unwrap();
+        let truncated = [
+            "$pbkdf2-sha256$i=1$$".to_string(),
+            format!("{}$$", params),
+            format!("{}${}$", params, salt),
+            format!("{}$${}", params, full_hash),
+            format!("{}${}${}", params, &salt[..4], full_hash),
+            format!("{}${}${}", params, salt, &full_hash[..8]),
+        ];
+        for stored in truncated {
+            assert!(
+                verify_password("anything", &stored).is_err(),
+                "{} was accepted",
+                stored
+            );
+            assert!(needs_rehash(&stored));
+        }
+    }
+}
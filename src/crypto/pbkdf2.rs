@@ -0,0 +1,76 @@
+use super::{sha256::DIGEST_LEN, HmacSha256};
+
+// PBKDF2 (RFC 8018) with HMAC-SHA256 as the pseudorandom function. Fills
+// `output` with key material derived from `password` and `salt`.
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    let keyed = HmacSha256::new(password);
+    for (index, block) in output.chunks_mut(DIGEST_LEN).enumerate() {
+        let mut mac = keyed.clone();
+        mac.update(salt);
+        mac.update(&(index as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut mac = keyed.clone();
+            mac.update(&u);
+            u = mac.finalize();
+            for (t_byte, u_byte) in t.iter_mut().zip(u) {
+                *t_byte ^= u_byte;
+            }
+        }
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    fn derive(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> Vec<u8> {
+        let mut output = vec![0u8; len];
+        pbkdf2_hmac_sha256(password, salt, iterations, &mut output);
+        output
+    }
+
+    // RFC 7914, section 11
+    #[test]
+    fn rfc7914() {
+        assert_eq!(
+            derive(b"passwd", b"salt", 1, 64),
+            from_hex(
+                "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc
+                 49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+            )
+        );
+    }
+
+    // The RFC 6070 inputs, with HMAC-SHA256 instead of HMAC-SHA1
+    #[test]
+    fn rfc6070_inputs() {
+        assert_eq!(
+            derive(b"password", b"salt", 1, 32),
+            from_hex("120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b")
+        );
+        assert_eq!(
+            derive(b"password", b"salt", 2, 32),
+            from_hex("ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43")
+        );
+        assert_eq!(
+            derive(b"password", b"salt", 4096, 32),
+            from_hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a")
+        );
+        // Output longer than one block, and cut short
+        assert_eq!(
+            derive(
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                40
+            ),
+            from_hex(
+                "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"
+            )
+        );
+    }
+}
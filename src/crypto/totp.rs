@@ -0,0 +1,142 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::hmac_sha1;
+use crate::error::Result;
+use crate::http_server::percent_encode;
+use crate::utils::{base32, random};
+
+// Time-based one-time passwords (RFC 6238) with the parameters every
+// authenticator app understands: HMAC-SHA1, 6 digits, 30 second steps.
+
+pub const SECRET_LEN: usize = 20;
+const DIGITS: u32 = 6;
+const PERIOD: u64 = 30;
+// Codes from this many steps either side of now are accepted, for clock drift
+const SKEW: u64 = 1;
+
+pub fn generate_secret() -> Result<Vec<u8>> {
+    random::bytes(SECRET_LEN)
+}
+
+// The code for one time step, HOTP from RFC 4226 with the step as counter.
+pub fn code_at(secret: &[u8], step: u64) -> String {
+    let hash = hmac_sha1(secret, &step.to_be_bytes());
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+pub fn current_step(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / PERIOD)
+        .unwrap_or(0)
+}
+
+// The time step `code` belongs to, if it is valid now. Steps up to and
+// including `last_used_step` are refused so a code can't be replayed.
+pub fn verify(secret: &[u8], code: &str, now: SystemTime, last_used_step: Option<u64>) -> Option<u64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let step = current_step(now);
+    let mut matched = None;
+    // Check every candidate so timing doesn't reveal which one matched
+    for candidate in step.saturating_sub(SKEW)..=step + SKEW {
+        let expected = code_at(secret, candidate);
+        if super::constant_time_eq(expected.as_bytes(), code.as_bytes())
+            && last_used_step.is_none_or(|last| candidate > last)
+        {
+            matched = Some(candidate);
+        }
+    }
+    matched
+}
+
+// For QR codes, see https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+pub fn otpauth_uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        base32::encode(secret),
+        percent_encode(issuer),
+        DIGITS,
+        PERIOD
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    // RFC 6238, appendix B, SHA-1. The RFC uses 8 digits; 6 digit codes are
+    // the same numbers mod 10^6, so their last six digits.
+    #[test]
+    fn rfc6238_known_answers() {
+        let cases = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ];
+        for (time, code) in cases {
+            assert_eq!(code_at(SECRET, current_step(at(time))), code[2..]);
+        }
+    }
+
+    #[test]
+    fn verify_allows_one_step_of_skew() {
+        let now = at(1111111111);
+        let step = current_step(now);
+        for candidate in [step - 1, step, step + 1] {
+            assert_eq!(
+                verify(SECRET, &code_at(SECRET, candidate), now, None),
+                Some(candidate)
+            );
+        }
+        for candidate in [step - 2, step + 2] {
+            assert_eq!(verify(SECRET, &code_at(SECRET, candidate), now, None), None);
+        }
+    }
+
+    #[test]
+    fn verify_refuses_replays() {
+        let now = at(1111111111);
+        let step = current_step(now);
+        let code = code_at(SECRET, step);
+        assert_eq!(verify(SECRET, &code, now, Some(step)), None);
+        assert_eq!(
+            verify(SECRET, &code_at(SECRET, step - 1), now, Some(step - 1)),
+            None
+        );
+        assert_eq!(verify(SECRET, &code, now, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn verify_checks_the_format() {
+        let now = at(1111111111);
+        let code = code_at(SECRET, current_step(now));
+        assert_eq!(
+            verify(SECRET, &format!("{} {}", &code[..3], &code[3..]), now, None),
+            Some(current_step(now))
+        );
+        assert_eq!(verify(SECRET, &code[..5], now, None), None);
+        assert_eq!(verify(SECRET, &format!("0{}", code), now, None), None);
+        assert_eq!(verify(SECRET, "", now, None), None);
+    }
+}
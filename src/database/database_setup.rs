@@ -1,27 +1,240 @@
-use std::sync::Arc;
+use std::{
+    sync::{Arc, Weak},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
 
 use crate::{
-    models::{Session, User}
+    models::{role, ApiKey, PasswordPolicy, Session, SessionConfig, User}
 };
-use crate::database::SimpleDB;
+use crate::database::{LoginThrottle, RegistrationLimit, SessionStore, SimpleDB};
 use crate::error::Result;
+use crate::crypto::{hash_password, Sha256};
+use crate::utils::{base64, logger, random};
+
+pub const API_KEY_PREFIX: &str = "wsk_";
+const API_KEY_BYTES: usize = 32;
 
 pub struct Database {
     pub users: Arc<SimpleDB<String, User>>,
-    pub sessions: Arc<SimpleDB<String, Session>>,
+    pub sessions: Arc<SessionStore>,
+    // Keyed by the SHA-256 hash of the token, see `ApiKey`.
+    pub api_keys: Arc<SimpleDB<String, ApiKey>>,
+    pub session_config: SessionConfig,
+    // Failed password attempts, shared by every way of logging in.
+    pub login_throttle: Arc<LoginThrottle>,
+    // Applies to passwords users choose, not to the seeded admin's.
+    pub password_policy: PasswordPolicy,
+    // How often one address may sign up, and how many users there may be.
+    pub registration_limit: Arc<RegistrationLimit>,
 }
 impl Database {
-    pub fn database_init() -> Result<Database> {
+    pub fn database_init(admin_password: &str) -> Result<Database> {
         let users: Arc<SimpleDB<String, User>> = Arc::new(SimpleDB::new());
-        let sessions: Arc<SimpleDB<String, Session>> = Arc::new(SimpleDB::new());
-        let admin_user = User {
-            username: "admin".to_string(),
-            password: "hunter12".to_string(),
-        };
+        let sessions = Arc::new(SessionStore::new());
+        let admin_user = User::new("admin", admin_password)?.role(role::ADMIN);
         users
             .insert(admin_user.username.clone(), admin_user)
             .map_err(|e| format!("error adding admin credentials to database {e}"))?;
 
-        Ok(Database { users, sessions })
+        Ok(Database {
+            users,
+            sessions,
+            api_keys: Arc::new(SimpleDB::new()),
+            session_config: SessionConfig::default(),
+            login_throttle: Arc::new(LoginThrottle::default()),
+            password_policy: PasswordPolicy::default(),
+            registration_limit: Arc::new(RegistrationLimit::default()),
+        })
+    }
+
+    pub fn session_config(mut self, session_config: SessionConfig) -> Self {
+        self.session_config = session_config;
+        self
+    }
+
+    // Sessions that haven't expired yet, oldest first.
+    pub fn active_sessions(&self) -> Result<Vec<Session>> {
+        let now = Instant::now();
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .all()?
+            .into_iter()
+            .filter(|session| !session.is_expired(&self.session_config, now))
+            .collect();
+        sessions.sort_by_key(|session| session.created_at);
+        Ok(sessions)
+    }
+
+    // Ends every session of `username`, returning how many there were.
+    pub fn revoke_user_sessions(&self, username: &str) -> Result<usize> {
+        let revoked = self.sessions.remove_for_user(username)?;
+        logger::info(&format!("Revoked {} sessions of user: {}", revoked, username));
+        Ok(revoked)
+    }
+
+    pub fn login_throttle(mut self, login_throttle: LoginThrottle) -> Self {
+        self.login_throttle = Arc::new(login_throttle);
+        self
+    }
+
+    pub fn password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = password_policy;
+        self
+    }
+
+    pub fn registration_limit(mut self, registration_limit: RegistrationLimit) -> Self {
+        self.registration_limit = Arc::new(registration_limit);
+        self
+    }
+
+    // Adds `user` unless the username is taken, returning whether it did.
+    pub fn create_user(&self, user: User) -> Result<bool> {
+        let username = user.username.clone();
+        let created = self.users.insert_new(username.clone(), user)?;
+        if created {
+            logger::info(&format!("Created user: {}", username));
+        }
+        Ok(created)
+    }
+
+    // Sets a new password and ends the user's other sessions, keeping only
+    // `keep_session` (the one that made the change) signed in.
+    pub fn change_password(
+        &self,
+        username: &str,
+        password: &str,
+        keep_session: Option<&str>,
+    ) -> Result<()> {
+        // Hashed before taking the lock, it takes a while
+        let password_hash = hash_password(password)?;
+        self.users
+            .modify(username.to_string(), |user| user.password_hash = password_hash)?
+            .ok_or_else(|| format!("No such user: {}", username))?;
+        let revoked = self.sessions.retain(|id, session| {
+            session.username != username || Some(id.as_str()) == keep_session
+        })?;
+        logger::info(&format!(
+            "Changed password of user: {}, revoked {} other sessions",
+            username, revoked
+        ));
+        Ok(())
     }
+
+    // Disables or re-enables a user, returning whether they exist. Disabling
+    // also ends their sessions.
+    pub fn set_user_disabled(&self, username: &str, disabled: bool) -> Result<bool> {
+        let exists = self
+            .users
+            .modify(username.to_string(), |user| user.disabled = disabled)?
+            .is_some();
+        if exists && disabled {
+            self.revoke_user_sessions(username)?;
+        }
+        if exists {
+            let action = if disabled { "Disabled" } else { "Enabled" };
+            logger::info(&format!("{} user: {}", action, username));
+        }
+        Ok(exists)
+    }
+
+    // Removes a user with their sessions and API keys, returning whether
+    // they existed.
+    pub fn delete_user(&self, username: &str) -> Result<bool> {
+        if self.users.remove(username.to_string())?.is_none() {
+            return Ok(false);
+        }
+        self.sessions.remove_for_user(username)?;
+        self.api_keys.retain(|_, api_key| api_key.username != username)?;
+        logger::info(&format!("Deleted user: {}", username));
+        Ok(true)
+    }
+
+    // Creates an API key for `username` and returns it together with its
+    // token. The token can't be recovered later.
+    pub fn create_api_key(
+        &self,
+        username: &str,
+        name: &str,
+        scopes: Vec<String>,
+        lifetime: Option<Duration>,
+        second_factor: bool,
+    ) -> Result<(ApiKey, String)> {
+        let token = format!("{}{}", API_KEY_PREFIX, random::token(API_KEY_BYTES)?);
+        let now = SystemTime::now();
+        let api_key = ApiKey {
+            id: random::token(9)?,
+            username: username.to_string(),
+            name: name.to_string(),
+            scopes,
+            created_at: now,
+            expires_at: lifetime.map(|lifetime| now + lifetime),
+            second_factor,
+        };
+        self.api_keys
+            .insert(api_key_hash(&token), api_key.clone())?;
+        logger::info(&format!("Created API key {} for user: {}", api_key.id, username));
+        Ok((api_key, token))
+    }
+
+    // The live key a token belongs to. Expired keys are removed on sight.
+    pub fn find_api_key(&self, token: &str) -> Result<Option<ApiKey>> {
+        let hash = api_key_hash(token);
+        match self.api_keys.get(hash.clone())? {
+            Some(api_key) if api_key.is_expired(SystemTime::now()) => {
+                self.api_keys.remove(hash)?;
+                Ok(None)
+            }
+            api_key => Ok(api_key),
+        }
+    }
+
+    pub fn api_keys_for(&self, username: &str) -> Result<Vec<ApiKey>> {
+        let mut api_keys = self.api_keys.values()?;
+        api_keys.retain(|api_key| api_key.username == username);
+        api_keys.sort_by_key(|api_key| api_key.created_at);
+        Ok(api_keys)
+    }
+
+    // Revokes `username`'s key with the given id, returning whether it existed.
+    pub fn revoke_api_key(&self, username: &str, id: &str) -> Result<bool> {
+        let removed = self
+            .api_keys
+            .retain(|_, api_key| !(api_key.username == username && api_key.id == id))?;
+        if removed > 0 {
+            logger::info(&format!("Revoked API key {} of user: {}", id, username));
+        }
+        Ok(removed > 0)
+    }
+
+    pub fn remove_expired_sessions(&self) -> Result<usize> {
+        let now = Instant::now();
+        self.sessions
+            .retain(|_, session| !session.is_expired(&self.session_config, now))
+    }
+
+    // Periodically removes expired sessions on a background thread. The
+    // thread only holds a weak reference and stops once the database is
+    // dropped.
+    pub fn spawn_session_reaper(self: &Arc<Self>) -> JoinHandle<()> {
+        let database: Weak<Database> = Arc::downgrade(self);
+        let interval = self.session_config.reap_interval;
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(database) = database.upgrade() else {
+                break;
+            };
+            match database.remove_expired_sessions() {
+                Ok(0) => {}
+                Ok(removed) => logger::info(&format!("Removed {} expired sessions", removed)),
+                Err(e) => logger::error(&format!("Failed to remove expired sessions: {}", e)),
+            }
+        })
+    }
+}
+
+// Tokens are long and random, so a fast unsalted hash is enough to keep a
+// leaked table from being usable.
+fn api_key_hash(token: &str) -> String {
+    base64::encode_url(&Sha256::digest(token.as_bytes()))
 }
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::Result;
+use crate::utils::logger;
+
+// Past this many tracked keys, stale entries are pruned on the next failure.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// How failed attempts for one kind of key are punished. The first
+// `free_attempts` failures cost nothing, after that each one doubles the wait
+// before the next attempt, starting at `base_delay` and capped at `max_delay`.
+// At `max_failures` the key is locked out for `lockout`. Failures older than
+// `forget_after` are forgotten.
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottleConfig {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub max_failures: u32,
+    pub lockout: Duration,
+    pub forget_after: Duration,
+}
+
+impl ThrottleConfig {
+    // Per username. Strict, one account only has one rightful owner.
+    pub fn username() -> Self {
+        ThrottleConfig {
+            free_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_failures: 10,
+            lockout: Duration::from_secs(15 * 60),
+            forget_after: Duration::from_secs(60 * 60),
+        }
+    }
+
+    // Per client address. Looser, since many users can share one address.
+    pub fn peer_addr() -> Self {
+        ThrottleConfig {
+            free_attempts: 10,
+            max_failures: 50,
+            ..ThrottleConfig::username()
+        }
+    }
+
+    fn delay_after(&self, failures: u32) -> Duration {
+        if failures <= self.free_attempts {
+            return Duration::ZERO;
+        }
+        let doublings = (failures - self.free_attempts - 1).min(31);
+        self.base_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+struct Tracker {
+    config: ThrottleConfig,
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl Tracker {
+    fn new(config: ThrottleConfig) -> Self {
+        Tracker {
+            config,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn retry_after(&self, key: &str, now: Instant) -> Result<Option<Duration>> {
+        let attempts = self
+            .attempts
+            .lock()
+            .map_err(|e| format!("Failed reading login attempts: {}", e))?;
+        Ok(attempts
+            .get(key)
+            .filter(|a| a.blocked_until > now)
+            .map(|a| a.blocked_until - now))
+    }
+
+    // Records a failure and returns the failure count, plus whether this
+    // failure just triggered a lockout.
+    fn record_failure(&self, key: &str, now: Instant) -> Result<(u32, bool)> {
+        let mut attempts = self
+            .attempts
+            .lock()
+            .map_err(|e| format!("Failed recording login attempt: {}", e))?;
+        let config = self.config;
+        if attempts.len() >= PRUNE_THRESHOLD {
+            attempts.retain(|_, a| {
+                a.blocked_until > now || now.duration_since(a.last_failure) < config.forget_after
+            });
+        }
+        let entry = attempts.entry(key.to_string()).or_insert(Attempts {
+            failures: 0,
+            last_failure: now,
+            blocked_until: now,
+        });
+        if now.duration_since(entry.last_failure) >= config.forget_after {
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+        entry.last_failure = now;
+        let locked = entry.failures >= config.max_failures;
+        entry.blocked_until = now
+            + if locked {
+                config.lockout
+            } else {
+                config.delay_after(entry.failures)
+            };
+        if locked {
+            // Start over once the lockout ends
+            entry.failures = 0;
+            entry.last_failure = entry.blocked_until;
+        }
+        Ok((entry.failures, locked))
+    }
+
+    fn clear(&self, key: &str) -> Result<()> {
+        self.attempts
+            .lock()
+            .map_err(|e| format!("Failed clearing login attempts: {}", e))?
+            .remove(key);
+        Ok(())
+    }
+}
+
+// Slows down and then locks out password guessing, tracked both per username
+// and per client address so neither spraying one password across accounts nor
+// hammering one account from many addresses goes unnoticed.
+pub struct LoginThrottle {
+    by_username: Tracker,
+    by_peer_addr: Tracker,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self::new(ThrottleConfig::username(), ThrottleConfig::peer_addr())
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(username: ThrottleConfig, peer_addr: ThrottleConfig) -> Self {
+        LoginThrottle {
+            by_username: Tracker::new(username),
+            by_peer_addr: Tracker::new(peer_addr),
+        }
+    }
+
+    // How long the caller has to wait before trying again, if at all.
+    pub fn retry_after(
+        &self,
+        username: &str,
+        peer_addr: Option<IpAddr>,
+    ) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        let by_username = self.by_username.retry_after(username, now)?;
+        let by_peer_addr = match peer_addr {
+            Some(addr) => self.by_peer_addr.retry_after(&addr.to_string(), now)?,
+            None => None,
+        };
+        Ok(by_username.max(by_peer_addr))
+    }
+
+    // Returns whether this failure locked the username or the address out.
+    pub fn record_failure(&self, username: &str, peer_addr: Option<IpAddr>) -> Result<bool> {
+        let now = Instant::now();
+        let (failures, locked) = self.by_username.record_failure(username, now)?;
+        if locked {
+            logger::error(&format!(
+                "Login locked out for user: {} after {} failed attempts",
+                username, self.by_username.config.max_failures
+            ));
+        } else if failures > self.by_username.config.free_attempts {
+            logger::info(&format!(
+                "Login throttled for user: {} after {} failed attempts",
+                username, failures
+            ));
+        }
+        let mut peer_locked = false;
+        if let Some(addr) = peer_addr {
+            (_, peer_locked) = self.by_peer_addr.record_failure(&addr.to_string(), now)?;
+            if peer_locked {
+                logger::error(&format!(
+                    "Login locked out for address: {} after {} failed attempts",
+                    addr, self.by_peer_addr.config.max_failures
+                ));
+            }
+        }
+        Ok(locked || peer_locked)
+    }
+
+    // A successful login clears the username's record. The address keeps its
+    // record, one valid account mustn't reset guessing at others.
+    pub fn record_success(&self, username: &str) -> Result<()> {
+        self.by_username.clear(username)
+    }
+}
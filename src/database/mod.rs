@@ -1,5 +1,11 @@
 pub mod database_setup;
+pub mod login_throttle;
+pub mod registration_limit;
+pub mod session_store;
 pub mod simple_db;
 
 pub use database_setup::*;
+pub use login_throttle::*;
+pub use registration_limit::*;
+pub use session_store::*;
 pub use simple_db::*;
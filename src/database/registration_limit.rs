@@ -0,0 +1,66 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::Result;
+
+// Past this many tracked addresses, stale ones are pruned on the next attempt.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Keeps anonymous sign ups from eating CPU (every one hashes a password) and
+// memory (every one adds a user). Each client address gets `per_addr`
+// attempts per `window`, and no one can register past `max_users`.
+pub struct RegistrationLimit {
+    pub per_addr: usize,
+    pub window: Duration,
+    pub max_users: usize,
+    attempts: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+}
+
+impl Default for RegistrationLimit {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(60 * 60), 10_000)
+    }
+}
+
+impl RegistrationLimit {
+    pub fn new(per_addr: usize, window: Duration, max_users: usize) -> Self {
+        RegistrationLimit {
+            per_addr,
+            window,
+            max_users,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Counts an attempt from `peer_addr`, or returns how long it has to wait
+    // when it used up its allowance. Requests without an address aren't
+    // limited.
+    pub fn record_attempt(&self, peer_addr: Option<IpAddr>) -> Result<Option<Duration>> {
+        let Some(addr) = peer_addr else {
+            return Ok(None);
+        };
+        let now = Instant::now();
+        let mut attempts = self
+            .attempts
+            .lock()
+            .map_err(|e| format!("Failed recording registration attempt: {}", e))?;
+        let window = self.window;
+        if attempts.len() >= PRUNE_THRESHOLD {
+            attempts.retain(|_, times| {
+                times.last().is_some_and(|&last| now.duration_since(last) < window)
+            });
+        }
+        let times = attempts.entry(addr).or_default();
+        times.retain(|&time| now.duration_since(time) < window);
+        if times.len() >= self.per_addr {
+            let oldest = times.first().copied().unwrap_or(now);
+            return Ok(Some(window.saturating_sub(now.duration_since(oldest))));
+        }
+        times.push(now);
+        Ok(None)
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+    time::Instant,
+};
+
+use crate::error::Result;
+use crate::models::{Session, SessionState};
+
+// Sessions keyed by id, with an index from username to that user's session
+// ids so all of a user's sessions can be found or revoked at once. Both maps
+// live behind one lock so they never disagree.
+#[derive(Default)]
+pub struct SessionStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    sessions: HashMap<String, Session>,
+    by_username: HashMap<String, HashSet<String>>,
+}
+
+impl Inner {
+    fn insert(&mut self, session_id: String, session: Session) -> Option<Session> {
+        self.by_username
+            .entry(session.username.clone())
+            .or_default()
+            .insert(session_id.clone());
+        let old = self.sessions.insert(session_id.clone(), session);
+        if let Some(old) = &old {
+            self.unindex(&old.username, &session_id);
+        }
+        old
+    }
+
+    fn remove(&mut self, session_id: &str) -> Option<Session> {
+        let removed = self.sessions.remove(session_id)?;
+        self.unindex(&removed.username, session_id);
+        Some(removed)
+    }
+
+    // Drops `session_id` from `username`'s index entry, unless the stored
+    // session still belongs to that user.
+    fn unindex(&mut self, username: &str, session_id: &str) {
+        if self
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| session.username == username)
+        {
+            return;
+        }
+        if let Some(ids) = self.by_username.get_mut(username) {
+            ids.remove(session_id);
+            if ids.is_empty() {
+                self.by_username.remove(username);
+            }
+        }
+    }
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        SessionStore::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Inner>> {
+        self.inner
+            .lock()
+            .map_err(|e| format!("Failed accessing session store: {}", e).into())
+    }
+
+    pub fn insert(&self, session_id: String, session: Session) -> Result<()> {
+        self.lock()?.insert(session_id, session);
+        Ok(())
+    }
+
+    pub fn get(&self, session_id: String) -> Result<Option<Session>> {
+        Ok(self.lock()?.sessions.get(&session_id).cloned())
+    }
+
+    pub fn update(&self, session_id: String, session: Session) -> Result<Option<Session>> {
+        Ok(self.lock()?.insert(session_id, session))
+    }
+
+    // Marks a session as active at `now`. Unlike `update` this never brings
+    // back a session that was removed in the meantime.
+    pub fn touch(&self, session_id: &str, now: Instant) -> Result<bool> {
+        match self.lock()?.sessions.get_mut(session_id) {
+            Some(session) => {
+                session.last_active = now;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Like `touch`, only changes a session that still exists.
+    pub fn set_state(&self, session_id: &str, state: SessionState) -> Result<bool> {
+        match self.lock()?.sessions.get_mut(session_id) {
+            Some(session) => {
+                session.state = state;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn remove(&self, session_id: String) -> Result<Option<Session>> {
+        Ok(self.lock()?.remove(&session_id))
+    }
+
+    // Keeps only the sessions `keep` returns true for, returning how many
+    // were removed.
+    pub fn retain(&self, mut keep: impl FnMut(&String, &Session) -> bool) -> Result<usize> {
+        let mut inner = self.lock()?;
+        let doomed: Vec<String> = inner
+            .sessions
+            .iter()
+            .filter(|(id, session)| !keep(id, session))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &doomed {
+            inner.remove(id);
+        }
+        Ok(doomed.len())
+    }
+
+    pub fn for_user(&self, username: &str) -> Result<Vec<Session>> {
+        let inner = self.lock()?;
+        let sessions = inner
+            .by_username
+            .get(username)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| inner.sessions.get(id).cloned())
+            .collect();
+        Ok(sessions)
+    }
+
+    // Ends every session of `username`, e.g. after a password change.
+    pub fn remove_for_user(&self, username: &str) -> Result<usize> {
+        let mut inner = self.lock()?;
+        let ids = inner.by_username.remove(username).unwrap_or_default();
+        for id in &ids {
+            inner.sessions.remove(id);
+        }
+        Ok(ids.len())
+    }
+
+    pub fn all(&self) -> Result<Vec<Session>> {
+        Ok(self.lock()?.sessions.values().cloned().collect())
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.lock()?.sessions.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.lock()?.sessions.is_empty())
+    }
+}
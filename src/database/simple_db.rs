@@ -14,6 +14,16 @@ where
     storage: Arc<Mutex<HashMap<K, V>>>,
 }
 
+impl<K, V> Default for SimpleDB<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> SimpleDB<K, V>
 where
     K: Eq + Hash + Clone,
@@ -28,16 +38,29 @@ where
         let mut storage = self
             .storage
             .lock()
-            .map_err(|e| format!("Failed inserting to database: {}", e.to_string()))?;
+            .map_err(|e| format!("Failed inserting to database: {}", e))?;
 
         storage.insert(key, value);
         Ok(())
     }
+    // Inserts unless `key` is taken, returning whether it did. Checking and
+    // inserting under one lock means two callers can't both claim a key.
+    pub fn insert_new(&self, key: K, value: V) -> Result<bool> {
+        let mut storage = self
+            .storage
+            .lock()
+            .map_err(|e| format!("Failed inserting to database: {}", e))?;
+        if storage.contains_key(&key) {
+            return Ok(false);
+        }
+        storage.insert(key, value);
+        Ok(true)
+    }
     pub fn get(&self, key: K) -> Result<Option<V>> {
         let storage = self
             .storage
             .lock()
-            .map_err(|e| format!("Failed reading from database: {}", e.to_string()))?;
+            .map_err(|e| format!("Failed reading from database: {}", e))?;
         let value = storage.get(&key).cloned();
         Ok(value)
     }
@@ -50,6 +73,17 @@ where
         Ok(old_value)
     }
 
+    // Changes the value in place while holding the lock, so concurrent
+    // read-modify-write cycles can't overwrite each other. `None` when `key`
+    // isn't present.
+    pub fn modify<R>(&self, key: K, change: impl FnOnce(&mut V) -> R) -> Result<Option<R>> {
+        let mut storage = self
+            .storage
+            .lock()
+            .map_err(|_| "Failed updating database")?;
+        Ok(storage.get_mut(&key).map(change))
+    }
+
     pub fn remove(&self, key: K) -> Result<Option<V>> {
         let mut storage = self
             .storage
@@ -58,4 +92,36 @@ where
         let removed_value = storage.remove(&key);
         Ok(removed_value)
     }
+
+    pub fn values(&self) -> Result<Vec<V>> {
+        let storage = self
+            .storage
+            .lock()
+            .map_err(|e| format!("Failed reading from database: {}", e))?;
+        Ok(storage.values().cloned().collect())
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        let storage = self
+            .storage
+            .lock()
+            .map_err(|e| format!("Failed reading from database: {}", e))?;
+        Ok(storage.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    // Keeps only the entries `keep` returns true for, returning how many
+    // were removed.
+    pub fn retain(&self, mut keep: impl FnMut(&K, &V) -> bool) -> Result<usize> {
+        let mut storage = self
+            .storage
+            .lock()
+            .map_err(|_| "Failed removing from database")?;
+        let before = storage.len();
+        storage.retain(|key, value| keep(key, value));
+        Ok(before - storage.len())
+    }
 }
@@ -0,0 +1,45 @@
+use crate::{
+    error::Result,
+    http_server::{
+        bad_request_response, global_audit_log, AuditQuery, ContentType, Request, Response,
+        ResponseBuilder, RouteHandler,
+    },
+    utils::Json,
+};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+// Searches the audit trail, newest first. Filters by `?username=` (who acted
+// or was acted upon), `?event=`, `?outcome=` and returns at most `?limit=`
+// events.
+pub struct ListAuditEventsHandler;
+
+impl RouteHandler for ListAuditEventsHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let audit_log = global_audit_log().ok_or("Audit log is not initialized")?;
+        let limit = match request.query_param("limit") {
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                _ => {
+                    return Ok(bad_request_response(format!(
+                        "limit must be between 1 and {}",
+                        MAX_LIMIT
+                    )))
+                }
+            },
+            None => DEFAULT_LIMIT,
+        };
+        let query = AuditQuery {
+            username: request.query_param("username"),
+            event: request.query_param("event"),
+            outcome: request.query_param("outcome"),
+            limit,
+        };
+        let events = audit_log.query(&query)?;
+        Ok(ResponseBuilder::new()
+            .content_type(ContentType::Json)
+            .body_string(Json::object().field("events", events).to_string())
+            .build())
+    }
+}
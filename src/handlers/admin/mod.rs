@@ -0,0 +1,72 @@
+pub mod audit;
+pub mod sessions;
+pub mod users;
+
+pub use audit::ListAuditEventsHandler;
+pub use sessions::{ListSessionsHandler, RevokeSessionsHandler};
+pub use users::{CreateUserHandler, DeleteUserHandler, ListUsersHandler, SetUserDisabledHandler};
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+use crate::models::role;
+
+// Internal tools. Read-only users can look, operators can also act, and only
+// admins manage users. Everyone needs a second factor here.
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new()
+        .require_role(&[role::ADMIN, role::OPERATOR, role::READ_ONLY])
+        .require_second_factor()
+        .add_route(
+            HttpMethod::get("/admin/sessions"),
+            ListSessionsHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::delete("/admin/sessions/:username")
+                .require_role(&[role::ADMIN, role::OPERATOR]),
+            RevokeSessionsHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::get("/admin/audit").require_role(&[role::ADMIN]),
+            ListAuditEventsHandler,
+        )
+        .group("/admin/users", |users| {
+            users
+                .require_role(&[role::ADMIN])
+                .add_route(
+                    HttpMethod::get("/"),
+                    ListUsersHandler {
+                        database: Arc::clone(&database),
+                    },
+                )
+                .add_route(
+                    HttpMethod::post("/"),
+                    CreateUserHandler {
+                        database: Arc::clone(&database),
+                    },
+                )
+                .add_route(
+                    HttpMethod::post("/:username/disable"),
+                    SetUserDisabledHandler {
+                        database: Arc::clone(&database),
+                        disabled: true,
+                    },
+                )
+                .add_route(
+                    HttpMethod::post("/:username/enable"),
+                    SetUserDisabledHandler {
+                        database: Arc::clone(&database),
+                        disabled: false,
+                    },
+                )
+                .add_route(
+                    HttpMethod::delete("/:username"),
+                    DeleteUserHandler { database },
+                )
+        })
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{json_response, AuditAction, AuditEvent, Request, Response, RouteHandler},
+    models::Session,
+    utils::{format_http_date, Json},
+};
+
+// Lists active sessions as JSON, optionally only those of `?username=`.
+// Session ids are left out, they are as good as a password.
+pub struct ListSessionsHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for ListSessionsHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let mut sessions = self.database.active_sessions()?;
+        if let Some(username) = request.query_param("username") {
+            sessions.retain(|session| session.username == username);
+        }
+        let sessions: Vec<Json> = sessions.iter().map(session_json).collect();
+        Ok(json_response(
+            200,
+            Json::object().field("sessions", sessions),
+        ))
+    }
+}
+
+// Revokes every session of the user in the path.
+pub struct RevokeSessionsHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for RevokeSessionsHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let username = request.param("username").ok_or("Missing username")?;
+        let revoked = self.database.revoke_user_sessions(username)?;
+        AuditEvent::success(AuditAction::SessionsRevoked)
+            .request(&request)
+            .target(username)
+            .detail(format!("{} sessions", revoked))
+            .record();
+        Ok(json_response(
+            200,
+            Json::object()
+                .field("username", username)
+                .field("revoked", revoked),
+        ))
+    }
+}
+
+fn session_json(session: &Session) -> Json {
+    Json::object()
+        .field("username", session.username.as_str())
+        .field("created_at", format_http_date(session.created_at_time()))
+        .field("last_active", format_http_date(session.last_active_time()))
+        .field("idle_seconds", session.last_active.elapsed().as_secs())
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        bad_request_response, json_response, AuditAction, AuditEvent, Request, Response, ResponseBuilder, RouteHandler,
+    },
+    models::{validate_username, User},
+    utils::Json,
+};
+
+// Lists every user with their roles and status, sorted by username.
+pub struct ListUsersHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for ListUsersHandler {
+    fn handle(&self, _request: Request) -> Result<Response> {
+        let mut users = self.database.users.values()?;
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        let users: Vec<Json> = users.iter().map(user_json).collect();
+        Ok(json_response(200, Json::object().field("users", users)))
+    }
+}
+
+// Creates a user from the form fields `username`, `password` and optionally
+// `roles` (comma separated). The password policy applies as for sign ups.
+pub struct CreateUserHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for CreateUserHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let form = request.form_urlencoded().unwrap_or_default();
+        let username = form.get("username").map(String::as_str).unwrap_or_default();
+        let password = form.get("password").map(String::as_str).unwrap_or_default();
+        if let Err(message) = validate_username(username)
+            .and_then(|()| self.database.password_policy.validate(username, password))
+        {
+            return Ok(bad_request_response(message));
+        }
+
+        let mut user = User::new(username, password)?;
+        if let Some(roles) = form.get("roles") {
+            for role in roles
+                .split(',')
+                .map(str::trim)
+                .filter(|role| !role.is_empty())
+            {
+                user = user.role(role);
+            }
+        }
+        let json = user_json(&user);
+        if !self.database.create_user(user)? {
+            return Ok(json_response(
+                409,
+                Json::object().field("error", "Username is taken"),
+            ));
+        }
+        AuditEvent::success(AuditAction::UserCreated)
+            .request(&request)
+            .target(username)
+            .record();
+        Ok(json_response(201, json))
+    }
+}
+
+// Disables or re-enables the user in the path. Disabling signs them out
+// everywhere and stops their API keys from working.
+pub struct SetUserDisabledHandler {
+    pub database: Arc<Database>,
+    pub disabled: bool,
+}
+
+impl RouteHandler for SetUserDisabledHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let username = request.param("username").ok_or("Missing username")?;
+        if self.disabled && is_caller(&request, username) {
+            return Ok(bad_request_response("You can't disable yourself"));
+        }
+        if !self.database.set_user_disabled(username, self.disabled)? {
+            return Ok(no_such_user());
+        }
+        let action = if self.disabled {
+            AuditAction::UserDisabled
+        } else {
+            AuditAction::UserEnabled
+        };
+        AuditEvent::success(action)
+            .request(&request)
+            .target(username)
+            .record();
+        Ok(json_response(
+            200,
+            Json::object()
+                .field("username", username)
+                .field("disabled", self.disabled),
+        ))
+    }
+}
+
+// Deletes the user in the path along with their sessions and API keys.
+pub struct DeleteUserHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for DeleteUserHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let username = request.param("username").ok_or("Missing username")?;
+        if is_caller(&request, username) {
+            return Ok(bad_request_response("You can't delete yourself"));
+        }
+        if !self.database.delete_user(username)? {
+            return Ok(no_such_user());
+        }
+        AuditEvent::success(AuditAction::UserDeleted)
+            .request(&request)
+            .target(username)
+            .record();
+        Ok(ResponseBuilder::new()
+            .status_code(204)
+            .reason_phrase("No Content".to_string())
+            .build())
+    }
+}
+
+// Admins acting on their own account could lock everyone out.
+fn is_caller(request: &Request, username: &str) -> bool {
+    request
+        .principal()
+        .is_some_and(|principal| principal.username == username)
+}
+
+fn user_json(user: &User) -> Json {
+    Json::object()
+        .field("username", user.username.as_str())
+        .field("roles", user.roles.clone())
+        .field("disabled", user.disabled)
+        .field("two_factor", user.two_factor_enabled())
+}
+
+fn no_such_user() -> Response {
+    json_response(404, Json::object().field("error", "No such user"))
+}
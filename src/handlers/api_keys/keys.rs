@@ -0,0 +1,138 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        bad_request_response, default_forbidden_response, json_response, AuditAction, AuditEvent, AuthMethod, Principal,
+        Request, Response, ResponseBuilder, RouteHandler,
+    },
+    models::ApiKey,
+    utils::{format_http_date, Json},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub struct ListApiKeysHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for ListApiKeysHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = key_manager(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        let api_keys: Vec<Json> = self
+            .database
+            .api_keys_for(&principal.username)?
+            .iter()
+            .map(api_key_json)
+            .collect();
+        Ok(json_response(
+            200,
+            Json::object().field("api_keys", api_keys),
+        ))
+    }
+}
+
+// Creates a key from the form fields `name`, `scopes` (comma separated, each
+// one a role the caller has) and optionally `expires_in_days`. The response is
+// the only time the token is shown.
+pub struct CreateApiKeyHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for CreateApiKeyHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = key_manager(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        let form = request.form_urlencoded().unwrap_or_default();
+        let name = form.get("name").map(String::as_str).unwrap_or_default();
+        let scopes: Vec<String> = form
+            .get("scopes")
+            .map(|scopes| {
+                scopes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|scope| !scope.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(scope) = scopes.iter().find(|scope| !principal.has_role(scope)) {
+            return Ok(bad_request_response(format!(
+                "You don't have the role {}",
+                scope
+            )));
+        }
+        let lifetime = match form.get("expires_in_days") {
+            Some(days) => match days.parse::<u64>() {
+                Ok(days) if days > 0 => Some(Duration::from_secs(days * SECONDS_PER_DAY)),
+                _ => return Ok(bad_request_response("Invalid expires_in_days")),
+            },
+            None => None,
+        };
+
+        let (api_key, token) = self.database.create_api_key(
+            &principal.username,
+            name,
+            scopes,
+            lifetime,
+            principal.second_factor,
+        )?;
+        AuditEvent::success(AuditAction::ApiKeyCreated)
+            .request(&request)
+            .detail(format!("key {}", api_key.id))
+            .record();
+        Ok(json_response(
+            201,
+            api_key_json(&api_key).field("token", token),
+        ))
+    }
+}
+
+pub struct RevokeApiKeyHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for RevokeApiKeyHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = key_manager(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        let id = request.param("id").ok_or("Missing API key id")?;
+        if self.database.revoke_api_key(&principal.username, id)? {
+            AuditEvent::success(AuditAction::ApiKeyRevoked)
+                .request(&request)
+                .detail(format!("key {}", id))
+                .record();
+            Ok(ResponseBuilder::new()
+                .status_code(204)
+                .reason_phrase("No Content".to_string())
+                .build())
+        } else {
+            Ok(json_response(
+                404,
+                Json::object().field("error", "No such API key"),
+            ))
+        }
+    }
+}
+
+// The caller, if they signed in through the login form. Keys and tokens must
+// not be able to mint permanent credentials or revoke other keys.
+fn key_manager(request: &Request) -> Result<Option<&Principal>> {
+    let principal = request.principal().ok_or("Request is not authenticated")?;
+    Ok((principal.method == AuthMethod::Session).then_some(principal))
+}
+
+fn api_key_json(api_key: &ApiKey) -> Json {
+    Json::object()
+        .field("id", api_key.id.as_str())
+        .field("name", api_key.name.as_str())
+        .field("scopes", api_key.scopes.clone())
+        .field("created_at", format_http_date(api_key.created_at))
+        .field("expires_at", api_key.expires_at.map(format_http_date))
+        .field("second_factor", api_key.second_factor)
+}
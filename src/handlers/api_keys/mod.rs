@@ -0,0 +1,30 @@
+pub mod keys;
+
+pub use keys::{CreateApiKeyHandler, ListApiKeysHandler, RevokeApiKeyHandler};
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+// Lets signed-in users manage their own API keys.
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new()
+        .require_auth()
+        .add_route(
+            HttpMethod::get("/account/api-keys"),
+            ListApiKeysHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::post("/account/api-keys"),
+            CreateApiKeyHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::delete("/account/api-keys/:id"),
+            RevokeApiKeyHandler { database },
+        )
+}
@@ -1,36 +1,25 @@
-use std::sync::Arc;
-
-use crate::{
-    database::Database,
-    http_server::{
-        AuthResult, AuthRouteHandler, ContentType, Request, Response, ResponseBuilder, RouteHandler,
-    },
-};
+use crate::http_server::{ContentType, Request, Response, ResponseBuilder, RouteHandler};
+use crate::utils::escape_html;
 
 use crate::error::Result;
 
-pub struct HomeHandler {
-    pub database: Arc<Database>,
-}
-impl AuthRouteHandler for HomeHandler {
-    fn database(&self) -> Arc<Database> {
-        Arc::clone(&self.database)
-    }
-}
+// Expects to be mounted behind `RequireAuth`.
+pub struct HomeHandler;
 
 impl RouteHandler for HomeHandler {
     fn handle(&self, request: Request) -> Result<Response> {
-        let body = include_str!("./home.html").to_string();
-
-        let login_redirect = ResponseBuilder::new().temp_redirect("/login").build();
+        let principal = request.principal().ok_or("Request is not authenticated")?;
+        let csrf_field = request
+            .csrf_token()
+            .map(|token| token.form_field())
+            .unwrap_or_default();
+        let body = include_str!("./home.html")
+            .replace("{{username}}", &escape_html(&principal.username))
+            .replace("{{csrf_field}}", &csrf_field);
 
-        match self.authenticate_session(request)? {
-            AuthResult::Authenticated => Ok(ResponseBuilder::new()
-                .content_type(ContentType::Html)
-                .body_string(body)
-                .build()),
-            AuthResult::SessionNotPresent => Ok(login_redirect),
-            AuthResult::SessionInvalid => Ok(login_redirect),
-        }
+        Ok(ResponseBuilder::new()
+            .content_type(ContentType::Html)
+            .body_string(body)
+            .build())
     }
 }
@@ -1,3 +1,14 @@
 pub mod get;
 
 pub use get::HomeHandler;
+
+use crate::http_server::{HttpMethod, RequireAuth, Router};
+
+// Expects the server to run `Authenticate` (or an optional `SessionAuth`) so
+// signed-in users arrive with a `Principal`; everyone else is sent to the
+// login page.
+pub fn router() -> Router {
+    Router::new()
+        .middleware(RequireAuth::new().redirect_to("/login"))
+        .add_route(HttpMethod::get("/home"), HomeHandler)
+}
@@ -6,8 +6,12 @@ use crate::error::Result;
 
 pub struct GetLoginHandler;
 impl RouteHandler for GetLoginHandler {
-    fn handle(&self, _request: Request) -> Result<Response> {
-        let html = include_str!("./login.html").to_string();
+    fn handle(&self, request: Request) -> Result<Response> {
+        let csrf_field = request
+            .csrf_token()
+            .map(|token| token.form_field())
+            .unwrap_or_default();
+        let html = include_str!("./login.html").replace("{{csrf_field}}", &csrf_field);
 
         Ok(ResponseBuilder::new()
             .content_type(ContentType::Html)
@@ -1,5 +1,33 @@
 pub mod get;
 pub mod post;
+pub mod two_factor;
 
 pub use get::GetLoginHandler;
 pub use post::PostLoginHandler;
+pub use two_factor::{GetTwoFactorHandler, PostTwoFactorHandler};
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new()
+        .add_route(HttpMethod::get("/login"), GetLoginHandler)
+        .add_route(
+            HttpMethod::post("/login"),
+            PostLoginHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::get("/login/2fa"),
+            GetTwoFactorHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::post("/login/2fa"),
+            PostTwoFactorHandler { database },
+        )
+}
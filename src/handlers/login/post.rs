@@ -1,10 +1,14 @@
-use std::{sync::Arc, time::Instant};
+use std::{sync::Arc, time::Duration};
 
 use crate::{
     database::Database,
-    http_server::{ContentType, Cookie, Request, Response, ResponseBuilder, RouteHandler},
-    models::Session,
-    utils::logger,
+    http_server::{
+        too_many_requests_response, AuditAction, AuditEvent, ContentType, Cookie, Request, Response, ResponseBuilder,
+        RouteHandler, SameSite, SESSION_COOKIE,
+    },
+    crypto::{hash_password, verify_dummy_password},
+    models::{Session, SessionState},
+    utils::{logger, Json},
     error::Result
 };
 
@@ -21,27 +25,92 @@ impl RouteHandler for PostLoginHandler {
             let username = get_form_value(&form_data, "username");
             let password = get_form_value(&form_data, "password");
 
-            if username.is_none() || password.is_none() {
+            let (Some(username), Some(password)) = (username, password) else {
                 return error_response;
+            };
+
+            let peer_ip = request.peer_addr.map(|addr| addr.ip());
+            if let Some(retry_after) = self.database.login_throttle.retry_after(username, peer_ip)? {
+                AuditEvent::failure(AuditAction::Throttled)
+                    .request(&request)
+                    .username(username)
+                    .record();
+                return Ok(too_many_requests_response(retry_after));
             }
 
-            return match self.database.users.get(username.unwrap().to_string())? {
-                Some(user) if &user.password == password.unwrap() => {
+            let user = self.database.users.get(username.to_string())?;
+            let authenticated = match &user {
+                Some(user) => user.verify_password(password)?,
+                None => {
+                    // Hash anyway so response times don't reveal which usernames exist
+                    verify_dummy_password(password);
+                    false
+                }
+            };
+
+            return match user {
+                Some(user) if authenticated && user.disabled => {
+                    logger::error(&format!("Login to disabled user: {}", user.username));
+                    AuditEvent::failure(AuditAction::Login)
+                        .request(&request)
+                        .username(&user.username)
+                        .detail("user is disabled")
+                        .record();
+                    error_response
+                }
+                Some(user) if authenticated => {
+                    if user.password_needs_rehash() {
+                        let new_hash = hash_password(password)?;
+                        // Leave it alone if the password changed in the meantime
+                        let upgraded = self.database.users.modify(user.username.clone(), |stored| {
+                            let unchanged = stored.password_hash == user.password_hash;
+                            if unchanged {
+                                stored.password_hash = new_hash;
+                            }
+                            unchanged
+                        })?;
+                        if upgraded == Some(true) {
+                            logger::info(&format!("Upgraded password hash for user: {}", user.username));
+                        }
+                    }
+                    if user.two_factor_enabled() {
+                        // Throttling carries on into the code step, so only
+                        // a complete login counts as a success
+                        logger::info(&format!("User: {} awaiting second factor", user.username));
+                        AuditEvent::success(AuditAction::Login)
+                            .request(&request)
+                            .username(&user.username)
+                            .detail("password accepted, second factor pending")
+                            .record();
+                        let session = Session::new(&user.username)?
+                            .state(SessionState::PendingSecondFactor);
+                        let max_age = self.database.session_config.pending_timeout;
+                        return start_session(&self.database, &request, session, max_age, "/login/2fa");
+                    }
                     // Login successful
+                    self.database.login_throttle.record_success(&user.username)?;
                     logger::info(format!("User: {} successful login", user.username).as_str());
-                    let session_id = Session::generate_session_id();
-                    let session = Session {
-                        username: user.username.clone(),
-                        session_id: session_id.clone(),
-                        last_active: Instant::now(),
-                    };
-                    self.database.sessions.insert(session_id.clone(), session)?;
-                    Ok(ResponseBuilder::new()
-                        .cookie(Cookie::new("session_id", session_id))
-                        .build())
+                    AuditEvent::success(AuditAction::Login)
+                        .request(&request)
+                        .username(&user.username)
+                        .record();
+                    let session = Session::new(&user.username)?;
+                    let max_age = self.database.session_config.absolute_timeout;
+                    start_session(&self.database, &request, session, max_age, "/home")
                 }
                 _ => {
                     logger::error("Invalid Login Credentials");
+                    AuditEvent::failure(AuditAction::Login)
+                        .request(&request)
+                        .username(username)
+                        .detail("invalid credentials")
+                        .record();
+                    if self.database.login_throttle.record_failure(username, peer_ip)? {
+                        AuditEvent::failure(AuditAction::Lockout)
+                            .request(&request)
+                            .username(username)
+                            .record();
+                    }
                     error_response
                 }
             }
@@ -54,6 +123,32 @@ impl RouteHandler for PostLoginHandler {
     }
 }
 
+// Stores `session` and answers with its cookie and, for the login page's
+// script, where to go next.
+pub(super) fn start_session(
+    database: &Database,
+    request: &Request,
+    session: Session,
+    max_age: Duration,
+    next: &str,
+) -> Result<Response> {
+    let session_id = session.session_id.clone();
+    database.sessions.insert(session_id.clone(), session)?;
+    let keys = request
+        .cookie_keys()
+        .ok_or("Cookie keys are not configured")?;
+    let cookie = Cookie::new(SESSION_COOKIE, session_id)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(max_age);
+    Ok(ResponseBuilder::new()
+        .cookie(keys.signed().sign(cookie))
+        .content_type(ContentType::Json)
+        .body_string(Json::object().field("next", next).to_string())
+        .build())
+}
+
 fn get_form_value<'a>(
     form_data: &'a std::collections::HashMap<String, String>,
     key: &str,
@@ -0,0 +1,135 @@
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        json_response, session_id, too_many_requests_response, AuditAction, AuditEvent, ContentType, Request, Response, ResponseBuilder,
+        RouteHandler,
+    },
+    models::{Session, SessionState},
+    utils::{logger, Json},
+};
+
+use super::post::start_session;
+
+// The second step of logging in for users with two-factor authentication,
+// reached with the pending session `PostLoginHandler` started.
+pub struct GetTwoFactorHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for GetTwoFactorHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        if pending_session(&self.database, &request)?.is_none() {
+            return Ok(ResponseBuilder::new().temp_redirect("/login").build());
+        }
+        let csrf_field = request
+            .csrf_token()
+            .map(|token| token.form_field())
+            .unwrap_or_default();
+        let html = include_str!("./login_2fa.html").replace("{{csrf_field}}", &csrf_field);
+
+        Ok(ResponseBuilder::new()
+            .content_type(ContentType::Html)
+            .body_string(html)
+            .build())
+    }
+}
+
+// Checks the `code` form field, a TOTP code or a recovery code. On success
+// the pending session is swapped for a fresh, fully authenticated one.
+pub struct PostTwoFactorHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for PostTwoFactorHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(pending) = pending_session(&self.database, &request)? else {
+            return Ok(json_response(
+                401,
+                Json::object()
+                    .field("error", "Login expired")
+                    .field("next", "/login"),
+            ));
+        };
+        let username = pending.username.clone();
+        let form = request.form_urlencoded().unwrap_or_default();
+        let code = form.get("code").map(String::as_str).unwrap_or_default();
+
+        let throttle = &self.database.login_throttle;
+        let peer_ip = request.peer_addr.map(|addr| addr.ip());
+        if let Some(retry_after) = throttle.retry_after(&username, peer_ip)? {
+            AuditEvent::failure(AuditAction::Throttled)
+                .request(&request)
+                .username(&username)
+                .record();
+            return Ok(too_many_requests_response(retry_after));
+        }
+
+        // Checked and used up under the database lock, so two requests racing
+        // with the same code can't both get in
+        let now = SystemTime::now();
+        let verified = self
+            .database
+            .users
+            .modify(username.clone(), |user| match &mut user.two_factor {
+                Some(two_factor) if two_factor.enabled && !user.disabled => {
+                    two_factor.verify(code, now)
+                }
+                _ => false,
+            })?
+            .unwrap_or(false);
+        if !verified {
+            logger::error(&format!("Invalid second factor for user: {}", username));
+            AuditEvent::failure(AuditAction::SecondFactor)
+                .request(&request)
+                .username(&username)
+                .record();
+            if throttle.record_failure(&username, peer_ip)? {
+                AuditEvent::failure(AuditAction::Lockout)
+                    .request(&request)
+                    .username(&username)
+                    .record();
+            }
+            return Ok(json_response(
+                401,
+                Json::object().field("error", "Invalid code"),
+            ));
+        }
+
+        throttle.record_success(&username)?;
+        logger::info(&format!("User: {} successful login with second factor", username));
+        AuditEvent::success(AuditAction::SecondFactor)
+            .request(&request)
+            .username(&username)
+            .record();
+        // A new id, so the one handed out before the second factor is worthless
+        self.database.sessions.remove(pending.session_id)?;
+        let session = Session::new(&username)?.state(SessionState::SecondFactorVerified);
+        let max_age = self.database.session_config.absolute_timeout;
+        start_session(&self.database, &request, session, max_age, "/home")
+    }
+}
+
+// The request's session, if it is waiting for a second factor and hasn't
+// timed out.
+fn pending_session(database: &Database, request: &Request) -> Result<Option<Session>> {
+    let Some(sid) = session_id(request)? else {
+        return Ok(None);
+    };
+    let Some(session) = database.sessions.get(sid)? else {
+        return Ok(None);
+    };
+    if session.state != SessionState::PendingSecondFactor {
+        return Ok(None);
+    }
+    if session.is_expired(&database.session_config, Instant::now()) {
+        database.sessions.remove(session.session_id)?;
+        return Ok(None);
+    }
+    Ok(Some(session))
+}
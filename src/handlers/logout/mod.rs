@@ -0,0 +1,12 @@
+pub mod post;
+
+pub use post::LogoutHandler;
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new().add_route(HttpMethod::post("/logout"), LogoutHandler { database })
+}
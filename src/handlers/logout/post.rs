@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        session_id, AuditAction, AuditEvent, Cookie, Request, Response, ResponseBuilder, RouteHandler, SESSION_COOKIE,
+    },
+    utils::logger,
+};
+
+// Ends the caller's session on the server and clears the cookie. POST only,
+// so with `Csrf` another site can't log users out.
+pub struct LogoutHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for LogoutHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        if let Some(session_id) = session_id(&request)? {
+            if let Some(session) = self.database.sessions.remove(session_id)? {
+                logger::info(&format!("User: {} logged out", session.username));
+                AuditEvent::success(AuditAction::Logout)
+                    .request(&request)
+                    .username(&session.username)
+                    .record();
+            }
+        }
+        Ok(ResponseBuilder::new()
+            .cookie(Cookie::removal(SESSION_COOKIE).path("/"))
+            .temp_redirect("/login")
+            .build())
+    }
+}
@@ -1,7 +1,15 @@
+pub mod admin;
+pub mod api_keys;
 pub mod css;
 pub mod home;
 pub mod login;
+pub mod logout;
+pub mod password;
+pub mod register;
+pub mod token;
+pub mod two_factor;
 
 pub use css::*;
 pub use home::HomeHandler;
-pub use login::*;
+pub use login::{GetLoginHandler, PostLoginHandler};
+pub use logout::LogoutHandler;
@@ -0,0 +1,15 @@
+pub mod post;
+
+pub use post::ChangePasswordHandler;
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new().add_route(
+        HttpMethod::post("/account/password").require_auth(),
+        ChangePasswordHandler { database },
+    )
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        default_forbidden_response, json_response, too_many_requests_response, AuditAction, AuditEvent, AuthMethod, Request,
+        Response, ResponseBuilder, RouteHandler,
+    },
+    utils::{logger, Json},
+};
+
+// Changes the caller's password from the `current_password` and
+// `new_password` form fields. Every other session of the user is signed out,
+// so whoever knew the old password loses access.
+pub struct ChangePasswordHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for ChangePasswordHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let principal = request.principal().ok_or("Request is not authenticated")?;
+        // Only the login form's session, not a key or token acting for the user
+        if principal.method != AuthMethod::Session {
+            return Ok(default_forbidden_response());
+        }
+        let username = principal.username.as_str();
+        let form = request.form_urlencoded().unwrap_or_default();
+        let current_password = form
+            .get("current_password")
+            .map(String::as_str)
+            .unwrap_or_default();
+        let new_password = form
+            .get("new_password")
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        // A stolen session shouldn't make guessing the password any easier
+        let throttle = &self.database.login_throttle;
+        let peer_ip = request.peer_addr.map(|addr| addr.ip());
+        if let Some(retry_after) = throttle.retry_after(username, peer_ip)? {
+            AuditEvent::failure(AuditAction::Throttled)
+                .request(&request)
+                .detail("password change")
+                .record();
+            return Ok(too_many_requests_response(retry_after));
+        }
+        let user = self
+            .database
+            .users
+            .get(username.to_string())?
+            .ok_or("User no longer exists")?;
+        if !user.verify_password(current_password)? {
+            logger::error(&format!("Wrong current password for user: {}", username));
+            AuditEvent::failure(AuditAction::PasswordChanged)
+                .request(&request)
+                .detail("wrong current password")
+                .record();
+            if throttle.record_failure(username, peer_ip)? {
+                AuditEvent::failure(AuditAction::Lockout)
+                    .request(&request)
+                    .record();
+            }
+            return Ok(json_response(
+                403,
+                Json::object().field("error", "Current password is wrong"),
+            ));
+        }
+        throttle.record_success(username)?;
+
+        if let Err(message) = self
+            .database
+            .password_policy
+            .validate(username, new_password)
+        {
+            return Ok(json_response(400, Json::object().field("error", message)));
+        }
+        if new_password == current_password {
+            return Ok(json_response(
+                400,
+                Json::object().field("error", "New password must differ from the current one"),
+            ));
+        }
+
+        let keep_session = principal
+            .session
+            .as_ref()
+            .map(|session| session.session_id.as_str());
+        self.database
+            .change_password(username, new_password, keep_session)?;
+        AuditEvent::success(AuditAction::PasswordChanged)
+            .request(&request)
+            .record();
+        Ok(ResponseBuilder::new()
+            .status_code(204)
+            .reason_phrase("No Content".to_string())
+            .build())
+    }
+}
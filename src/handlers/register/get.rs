@@ -0,0 +1,21 @@
+use crate::{
+    http_server::{ContentType, Request, Response, ResponseBuilder, RouteHandler},
+};
+
+use crate::error::Result;
+
+pub struct GetRegisterHandler;
+impl RouteHandler for GetRegisterHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let csrf_field = request
+            .csrf_token()
+            .map(|token| token.form_field())
+            .unwrap_or_default();
+        let html = include_str!("./register.html").replace("{{csrf_field}}", &csrf_field);
+
+        Ok(ResponseBuilder::new()
+            .content_type(ContentType::Html)
+            .body_string(html)
+            .build())
+    }
+}
@@ -0,0 +1,19 @@
+pub mod get;
+pub mod post;
+
+pub use get::GetRegisterHandler;
+pub use post::PostRegisterHandler;
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+pub fn router(database: Arc<Database>) -> Router {
+    Router::new()
+        .add_route(HttpMethod::get("/register"), GetRegisterHandler)
+        .add_route(
+            HttpMethod::post("/register"),
+            PostRegisterHandler { database },
+        )
+}
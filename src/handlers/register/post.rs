@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use crate::{
+    database::Database,
+    error::Result,
+    http_server::{
+        json_response, too_many_requests_response, AuditAction, AuditEvent, Request, Response,
+        RouteHandler,
+    },
+    models::{validate_username, User},
+    utils::{logger, Json},
+};
+
+// Self-service sign up from the `username` and `password` form fields. New
+// users have no roles; the client is sent to the login page afterwards.
+// Limited by `Database::registration_limit`.
+pub struct PostRegisterHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for PostRegisterHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let limit = &self.database.registration_limit;
+        let peer_ip = request.peer_addr.map(|addr| addr.ip());
+        if let Some(retry_after) = limit.record_attempt(peer_ip)? {
+            AuditEvent::failure(AuditAction::Throttled)
+                .request(&request)
+                .detail("registration")
+                .record();
+            return Ok(too_many_requests_response(retry_after));
+        }
+        let form = request.form_urlencoded().unwrap_or_default();
+        let username = form.get("username").map(String::as_str).unwrap_or_default();
+        let password = form.get("password").map(String::as_str).unwrap_or_default();
+
+        if let Err(message) = validate_username(username)
+            .and_then(|()| self.database.password_policy.validate(username, password))
+        {
+            return Ok(json_response(400, Json::object().field("error", message)));
+        }
+        // Checked before hashing the password. Concurrent sign ups can still
+        // go a few over, which is fine for a safety limit.
+        if self.database.users.len()? >= limit.max_users {
+            logger::error("Registration refused, the user limit is reached");
+            return Ok(json_response(
+                403,
+                Json::object().field("error", "Registration is closed"),
+            ));
+        }
+        if !self.database.create_user(User::new(username, password)?)? {
+            return Ok(json_response(
+                409,
+                Json::object().field("error", "Username is taken"),
+            ));
+        }
+        AuditEvent::success(AuditAction::UserCreated)
+            .request(&request)
+            .username(username)
+            .detail("registration")
+            .record();
+        Ok(json_response(
+            201,
+            Json::object()
+                .field("username", username)
+                .field("next", "/login"),
+        ))
+    }
+}
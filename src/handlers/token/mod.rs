@@ -0,0 +1,21 @@
+pub mod post;
+
+pub use post::IssueTokenHandler;
+
+use std::{sync::Arc, time::Duration};
+
+use crate::crypto::Jwt;
+use crate::http_server::{HttpMethod, Router};
+
+// Trades a session or password for a short-lived JWT, for services that
+// check tokens without access to the session store. Tokens and API keys can't
+// be exchanged, or a token could be renewed forever.
+pub fn router(jwt: Arc<Jwt>) -> Router {
+    Router::new().add_route(
+        HttpMethod::post("/account/token").require_auth(),
+        IssueTokenHandler {
+            jwt,
+            lifetime: Duration::from_secs(15 * 60),
+        },
+    )
+}
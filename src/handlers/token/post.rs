@@ -0,0 +1,42 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    crypto::Jwt,
+    error::Result,
+    http_server::{
+        default_forbidden_response, AuthMethod, ContentType, Request, Response, ResponseBuilder,
+        RouteHandler,
+    },
+    utils::Json,
+};
+
+pub struct IssueTokenHandler {
+    pub jwt: Arc<Jwt>,
+    pub lifetime: Duration,
+}
+
+impl RouteHandler for IssueTokenHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let principal = request.principal().ok_or("Request is not authenticated")?;
+        // A token or API key could otherwise renew itself forever, outliving
+        // its expiry and whatever happens to the user
+        if !matches!(principal.method, AuthMethod::Session | AuthMethod::Basic) {
+            return Ok(default_forbidden_response());
+        }
+        let mut claims = self
+            .jwt
+            .claims(&principal.username, principal.roles.clone(), self.lifetime);
+        claims.auth_methods.push("pwd".to_string());
+        if principal.second_factor {
+            claims.auth_methods.push("otp".to_string());
+        }
+        let body = Json::object()
+            .field("token", self.jwt.sign(&claims)?)
+            .field("token_type", "Bearer")
+            .field("expires_in", self.lifetime.as_secs());
+        Ok(ResponseBuilder::new()
+            .content_type(ContentType::Json)
+            .body_string(body.to_string())
+            .build())
+    }
+}
@@ -0,0 +1,208 @@
+use std::{sync::Arc, time::SystemTime};
+
+use crate::{
+    crypto::totp,
+    database::Database,
+    error::Result,
+    http_server::{
+        bad_request_response, default_forbidden_response, json_response, too_many_requests_response, AuditAction, AuditEvent,
+        AuthMethod, Principal, Request, Response, ResponseBuilder, RouteHandler,
+    },
+    models::{SessionState, TwoFactor},
+    utils::{base32, logger, Json},
+};
+
+// Starts enrollment with a new secret, returned both in base32 for typing in
+// and as an `otpauth://` URI for QR codes. It isn't enforced until confirmed.
+pub struct EnrollTwoFactorHandler {
+    pub database: Arc<Database>,
+    pub issuer: String,
+}
+
+impl RouteHandler for EnrollTwoFactorHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = account_owner(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        let two_factor = TwoFactor::new()?;
+        let secret = two_factor.secret.clone();
+        let enrolled = self
+            .database
+            .users
+            .modify(principal.username.clone(), |user| {
+                if user.two_factor_enabled() {
+                    return false;
+                }
+                user.two_factor = Some(two_factor);
+                true
+            })?
+            .ok_or("User no longer exists")?;
+        if !enrolled {
+            return Ok(json_response(
+                409,
+                Json::object().field("error", "Two-factor authentication is already enabled"),
+            ));
+        }
+        Ok(json_response(
+            200,
+            Json::object()
+                .field("secret", base32::encode(&secret))
+                .field(
+                    "otpauth_uri",
+                    totp::otpauth_uri(&secret, &self.issuer, &principal.username),
+                ),
+        ))
+    }
+}
+
+// Turns the second factor on once the `code` form field shows the
+// authenticator app was set up right. Answers with the recovery codes, which
+// are never shown again.
+pub struct ConfirmTwoFactorHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for ConfirmTwoFactorHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = account_owner(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        if let Some(response) = throttled(&self.database, &request, &principal.username)? {
+            return Ok(response);
+        }
+        let code = form_code(&request);
+        let now = SystemTime::now();
+        let recovery_codes = self
+            .database
+            .users
+            .modify(principal.username.clone(), |user| -> Result<Option<Vec<String>>> {
+                let Some(two_factor) = user.two_factor.as_mut().filter(|t| !t.enabled) else {
+                    return Ok(None);
+                };
+                if !two_factor.verify(&code, now) {
+                    return Ok(None);
+                }
+                two_factor.enabled = true;
+                two_factor.generate_recovery_codes().map(Some)
+            })?
+            .ok_or("User no longer exists")??;
+        let Some(recovery_codes) = recovery_codes else {
+            let action = AuditAction::TwoFactorEnabled;
+            record_invalid_code(&self.database, &request, &principal.username, action)?;
+            return Ok(bad_request_response("Invalid code, or no enrollment in progress"));
+        };
+        self.database.login_throttle.record_success(&principal.username)?;
+
+        // This session just proved the second factor, no need to log in again
+        if let Some(session) = &principal.session {
+            self.database
+                .sessions
+                .set_state(&session.session_id, SessionState::SecondFactorVerified)?;
+        }
+        logger::info(&format!(
+            "User: {} enabled two-factor authentication",
+            principal.username
+        ));
+        AuditEvent::success(AuditAction::TwoFactorEnabled)
+            .request(&request)
+            .record();
+        Ok(json_response(
+            200,
+            Json::object().field("recovery_codes", recovery_codes),
+        ))
+    }
+}
+
+// Turns the second factor off. Takes a current code or a recovery code in the
+// `code` form field, so a hijacked session alone can't do it; wrong codes
+// count towards the login throttle.
+pub struct DisableTwoFactorHandler {
+    pub database: Arc<Database>,
+}
+
+impl RouteHandler for DisableTwoFactorHandler {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let Some(principal) = account_owner(&request)? else {
+            return Ok(default_forbidden_response());
+        };
+        if let Some(response) = throttled(&self.database, &request, &principal.username)? {
+            return Ok(response);
+        }
+        let code = form_code(&request);
+        let now = SystemTime::now();
+        let disabled = self
+            .database
+            .users
+            .modify(principal.username.clone(), |user| {
+                let verified = match &mut user.two_factor {
+                    Some(two_factor) if two_factor.enabled => two_factor.verify(&code, now),
+                    _ => false,
+                };
+                if verified {
+                    user.two_factor = None;
+                }
+                verified
+            })?
+            .ok_or("User no longer exists")?;
+        if !disabled {
+            let action = AuditAction::TwoFactorDisabled;
+            record_invalid_code(&self.database, &request, &principal.username, action)?;
+            return Ok(bad_request_response(
+                "Invalid code, or two-factor authentication is not enabled",
+            ));
+        }
+        self.database.login_throttle.record_success(&principal.username)?;
+        logger::info(&format!(
+            "User: {} disabled two-factor authentication",
+            principal.username
+        ));
+        AuditEvent::success(AuditAction::TwoFactorDisabled)
+            .request(&request)
+            .record();
+        Ok(ResponseBuilder::new()
+            .status_code(204)
+            .reason_phrase("No Content".to_string())
+            .build())
+    }
+}
+
+// The caller, if they signed in through the login form. Other credentials
+// can't stand in for the account's own login when changing how it is secured.
+fn account_owner(request: &Request) -> Result<Option<&Principal>> {
+    let principal = request.principal().ok_or("Request is not authenticated")?;
+    Ok((principal.method == AuthMethod::Session).then_some(principal))
+}
+
+// Codes are only six digits, so guessing them is throttled like logins are.
+fn throttled(database: &Database, request: &Request, username: &str) -> Result<Option<Response>> {
+    let peer_ip = request.peer_addr.map(|addr| addr.ip());
+    let Some(retry_after) = database.login_throttle.retry_after(username, peer_ip)? else {
+        return Ok(None);
+    };
+    AuditEvent::failure(AuditAction::Throttled).request(request).record();
+    Ok(Some(too_many_requests_response(retry_after)))
+}
+
+fn record_invalid_code(
+    database: &Database,
+    request: &Request,
+    username: &str,
+    action: AuditAction,
+) -> Result<()> {
+    AuditEvent::failure(action)
+        .request(request)
+        .detail("invalid code")
+        .record();
+    let peer_ip = request.peer_addr.map(|addr| addr.ip());
+    if database.login_throttle.record_failure(username, peer_ip)? {
+        AuditEvent::failure(AuditAction::Lockout).request(request).record();
+    }
+    Ok(())
+}
+
+fn form_code(request: &Request) -> String {
+    request
+        .form_urlencoded()
+        .and_then(|mut form| form.remove("code"))
+        .unwrap_or_default()
+}
@@ -0,0 +1,32 @@
+pub mod account;
+
+pub use account::{ConfirmTwoFactorHandler, DisableTwoFactorHandler, EnrollTwoFactorHandler};
+
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::http_server::{HttpMethod, Router};
+
+// Lets signed-in users set up and remove TOTP two-factor authentication.
+// `issuer` is the name authenticator apps show next to the account.
+pub fn router(database: Arc<Database>, issuer: &str) -> Router {
+    Router::new()
+        .require_auth()
+        .add_route(
+            HttpMethod::post("/account/2fa/enroll"),
+            EnrollTwoFactorHandler {
+                database: Arc::clone(&database),
+                issuer: issuer.to_string(),
+            },
+        )
+        .add_route(
+            HttpMethod::post("/account/2fa/confirm"),
+            ConfirmTwoFactorHandler {
+                database: Arc::clone(&database),
+            },
+        )
+        .add_route(
+            HttpMethod::post("/account/2fa/disable"),
+            DisableTwoFactorHandler { database },
+        )
+}
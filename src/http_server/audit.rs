@@ -0,0 +1,245 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::error::Result;
+use crate::http_server::Request;
+use crate::utils::{format_rfc3339, logger, Json};
+
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+// Events are only recorded once this was called, e.g. with "logs/audit.log".
+pub fn init_global_audit_log(path: impl AsRef<Path>) -> Result<()> {
+    AUDIT_LOG
+        .set(AuditLog::open(path)?)
+        .map_err(|_| "Audit log is already initialized".into())
+}
+
+pub fn global_audit_log() -> Option<&'static AuditLog> {
+    AUDIT_LOG.get()
+}
+
+// Security relevant things that happened, for the audit trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    SecondFactor,
+    Logout,
+    // Refused before the credentials were even checked, see `LoginThrottle`
+    Throttled,
+    Lockout,
+    AuthenticationFailed,
+    AccessDenied,
+    CsrfRejected,
+    PasswordChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    UserCreated,
+    UserDisabled,
+    UserEnabled,
+    UserDeleted,
+    SessionsRevoked,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::SecondFactor => "second_factor",
+            AuditAction::Logout => "logout",
+            AuditAction::Throttled => "throttled",
+            AuditAction::Lockout => "lockout",
+            AuditAction::AuthenticationFailed => "authentication_failed",
+            AuditAction::AccessDenied => "access_denied",
+            AuditAction::CsrfRejected => "csrf_rejected",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::TwoFactorEnabled => "two_factor_enabled",
+            AuditAction::TwoFactorDisabled => "two_factor_disabled",
+            AuditAction::UserCreated => "user_created",
+            AuditAction::UserDisabled => "user_disabled",
+            AuditAction::UserEnabled => "user_enabled",
+            AuditAction::UserDeleted => "user_deleted",
+            AuditAction::SessionsRevoked => "sessions_revoked",
+            AuditAction::ApiKeyCreated => "api_key_created",
+            AuditAction::ApiKeyRevoked => "api_key_revoked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+        }
+    }
+}
+
+// One line of the audit trail. `username` is who acted, when known, and
+// `target` the user acted upon when that is someone else.
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub timestamp: SystemTime,
+    pub action: AuditAction,
+    pub outcome: Outcome,
+    pub username: Option<String>,
+    pub target: Option<String>,
+    pub peer_addr: Option<String>,
+    pub request_id: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, outcome: Outcome) -> Self {
+        AuditEvent {
+            timestamp: SystemTime::now(),
+            action,
+            outcome,
+            username: None,
+            target: None,
+            peer_addr: None,
+            request_id: None,
+            detail: None,
+        }
+    }
+
+    pub fn success(action: AuditAction) -> Self {
+        AuditEvent::new(action, Outcome::Success)
+    }
+
+    pub fn failure(action: AuditAction) -> Self {
+        AuditEvent::new(action, Outcome::Failure)
+    }
+
+    // Takes the peer address, the request id and, if authenticated, the
+    // username from `request`.
+    pub fn request(mut self, request: &Request) -> Self {
+        self.peer_addr = request.peer_addr.map(|addr| addr.ip().to_string());
+        self.request_id = request.request_id().map(String::from);
+        if let Some(principal) = request.principal() {
+            self.username = Some(principal.username.clone());
+        }
+        self
+    }
+
+    pub fn username(mut self, username: impl ToString) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn detail(mut self, detail: impl ToString) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    // Appends the event to the global audit log, if there is one.
+    pub fn record(self) {
+        if let Some(audit_log) = global_audit_log() {
+            audit_log.record(&self);
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object()
+            .field("timestamp", format_rfc3339(self.timestamp))
+            .field("event", self.action.as_str())
+            .field("outcome", self.outcome.as_str())
+            .field("username", self.username.clone())
+            .field("target", self.target.clone())
+            .field("peer_addr", self.peer_addr.clone())
+            .field("request_id", self.request_id.clone())
+            .field("detail", self.detail.clone())
+    }
+}
+
+// What to look for in the audit trail. Unset fields match everything, and
+// `username` matches both who acted and who was acted upon.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub username: Option<String>,
+    pub event: Option<String>,
+    pub outcome: Option<String>,
+    pub limit: usize,
+}
+
+impl AuditQuery {
+    fn matches(&self, event: &Json) -> bool {
+        let field = |key: &str| event.get(key).and_then(Json::as_str);
+        let username_matches = self.username.as_deref().is_none_or(|username| {
+            field("username") == Some(username) || field("target") == Some(username)
+        });
+        username_matches
+            && self.event.as_deref().is_none_or(|e| field("event") == Some(e))
+            && self.outcome.as_deref().is_none_or(|o| field("outcome") == Some(o))
+    }
+}
+
+// An append-only file of audit events, one JSON object per line.
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<AuditLog> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(AuditLog {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    // Failing to write is logged rather than returned, an audit hiccup
+    // shouldn't fail the request that caused it.
+    pub fn record(&self, event: &AuditEvent) {
+        let line = format!("{}\n", event.to_json());
+        let written = self
+            .file
+            .lock()
+            .map_err(|e| e.to_string())
+            .and_then(|mut file| file.write_all(line.as_bytes()).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            logger::error(&format!("Failed to write audit event: {}", e));
+        }
+    }
+
+    // The newest events matching `query`, newest first.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<Json>> {
+        // Hold the lock so a half written line can't be read
+        let _file = self.file.lock().map_err(|_| "Failed reading audit log")?;
+        let reader = BufReader::new(File::open(&self.path)?);
+        let mut matches = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            match Json::parse(&line) {
+                Ok(event) if query.matches(&event) => matches.push(event),
+                Ok(_) => {}
+                Err(e) => logger::error(&format!("Skipping malformed audit line: {}", e)),
+            }
+        }
+        matches.reverse();
+        matches.truncate(query.limit);
+        Ok(matches)
+    }
+}
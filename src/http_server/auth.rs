@@ -1,28 +1,114 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 use crate::database::Database;
 use crate::http_server::{Request, RouteHandler};
+use crate::models::{Session, SessionState};
 
 use crate::error::Result;
 
+pub const SESSION_COOKIE: &str = "session_id";
+
+// Who a request was made by. Authentication middleware attaches it to the
+// request's extensions, handlers read it with `Request::principal`.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub username: String,
+    // Set when the request was authenticated by its session cookie.
+    pub session: Option<Session>,
+    pub roles: Vec<String>,
+    pub method: AuthMethod,
+    // Whether a second factor backs these credentials, see `RequireSecondFactor`.
+    pub second_factor: bool,
+}
+
+// How a `Principal` proved who they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Session,
+    Basic,
+    ApiKey,
+    Jwt,
+}
+
+impl AuthMethod {
+    // Whether the credentials came in a header a browser never sends on its
+    // own, which makes the request immune to CSRF.
+    pub fn is_explicit(&self) -> bool {
+        matches!(self, AuthMethod::ApiKey | AuthMethod::Jwt)
+    }
+}
+
+impl Principal {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
 pub enum AuthResult {
-    Authenticated,
+    Authenticated(Principal),
     SessionNotPresent,
     SessionInvalid,
+    SessionExpired,
+    // The password was checked but the second factor not yet.
+    SecondFactorPending(Session),
 }
 
 pub trait AuthRouteHandler: RouteHandler {
     fn database(&self) -> Arc<Database>;
 
-    fn authenticate_session(&self, request: Request) -> Result<AuthResult> {
-        let cookies = request.cookies();
-        let session_id = cookies.iter().find(|c| c.name == "session_id").cloned();
-
-        match session_id {
-            Some(sid) => match self.database().sessions.get(sid.value)? {
-                Some(_) => Ok(AuthResult::Authenticated),
-                None => Ok(AuthResult::SessionInvalid),
-            },
-            None => Ok(AuthResult::SessionNotPresent),
-        }
+    fn authenticate_session(&self, request: &Request) -> Result<AuthResult> {
+        authenticate_session(&self.database(), request)
+    }
+}
+
+// The id from the request's session cookie, if it carries one with a valid
+// signature. Says nothing about whether that session still exists.
+pub fn session_id(request: &Request) -> Result<Option<String>> {
+    let keys = request
+        .cookie_keys()
+        .ok_or("Cookie keys are not configured")?;
+    Ok(keys
+        .signed()
+        .get(request, SESSION_COOKIE)
+        .map(|cookie| cookie.value))
+}
+
+pub fn authenticate_session(database: &Database, request: &Request) -> Result<AuthResult> {
+    let Some(sid) = session_id(request)? else {
+        return Ok(AuthResult::SessionNotPresent);
+    };
+    let Some(mut session) = database.sessions.get(sid)? else {
+        return Ok(AuthResult::SessionInvalid);
+    };
+    let now = Instant::now();
+    if session.is_expired(&database.session_config, now) {
+        database.sessions.remove(session.session_id)?;
+        return Ok(AuthResult::SessionExpired);
+    }
+    // Sessions of deleted or disabled users die with them
+    let Some(user) = database
+        .users
+        .get(session.username.clone())?
+        .filter(|user| !user.disabled)
+    else {
+        database.sessions.remove(session.session_id)?;
+        return Ok(AuthResult::SessionInvalid);
+    };
+    if session.state == SessionState::PendingSecondFactor {
+        return Ok(AuthResult::SecondFactorPending(session));
+    }
+    // Sliding renewal: every authenticated request resets the idle timer
+    if !database.sessions.touch(&session.session_id, now)? {
+        return Ok(AuthResult::SessionInvalid);
     }
+    session.last_active = now;
+    // Turning the second factor off downgrades sessions that used it
+    let second_factor =
+        session.state == SessionState::SecondFactorVerified && user.two_factor_enabled();
+    Ok(AuthResult::Authenticated(Principal {
+        username: user.username,
+        session: Some(session),
+        roles: user.roles,
+        method: AuthMethod::Session,
+        second_factor,
+    }))
 }
@@ -0,0 +1,251 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::crypto::{verify_dummy_password, Jwt};
+use crate::database::{Database, API_KEY_PREFIX};
+use crate::error::Result;
+use crate::http_server::{authenticate_session, AuditAction, AuditEvent, AuthMethod, AuthResult, Principal, Request};
+use crate::utils::{base64, logger};
+
+pub const DEFAULT_REALM: &str = "web_server";
+
+pub enum Authentication {
+    Authenticated(Principal),
+    // The request carries no credentials of this kind
+    NotPresent,
+    // It does, but they are wrong
+    Rejected,
+    // Too many wrong attempts, try again after the duration
+    Throttled(Duration),
+}
+
+// One way of recognising who sent a request. `Authenticate` tries a list of
+// them in order.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, request: &Request) -> Result<Authentication>;
+
+    // The `WWW-Authenticate` challenge for 401 responses, if the scheme has one.
+    fn challenge(&self) -> Option<String> {
+        None
+    }
+}
+
+// The session cookie. A missing, invalid or expired session just leaves the
+// request anonymous, a browser has no way to answer a challenge for it.
+pub struct SessionAuthenticator {
+    pub database: Arc<Database>,
+}
+
+impl SessionAuthenticator {
+    pub fn new(database: Arc<Database>) -> Self {
+        SessionAuthenticator { database }
+    }
+}
+
+impl Authenticator for SessionAuthenticator {
+    fn authenticate(&self, request: &Request) -> Result<Authentication> {
+        match authenticate_session(&self.database, request)? {
+            AuthResult::Authenticated(principal) => Ok(Authentication::Authenticated(principal)),
+            _ => Ok(Authentication::NotPresent),
+        }
+    }
+}
+
+// `Authorization: Basic` against the users table, subject to the same login
+// throttle as the login form.
+pub struct BasicAuthenticator {
+    pub database: Arc<Database>,
+    pub realm: String,
+}
+
+impl BasicAuthenticator {
+    pub fn new(database: Arc<Database>) -> Self {
+        BasicAuthenticator {
+            database,
+            realm: DEFAULT_REALM.to_string(),
+        }
+    }
+}
+
+impl Authenticator for BasicAuthenticator {
+    fn authenticate(&self, request: &Request) -> Result<Authentication> {
+        let Some(credentials) = authorization(request, "Basic") else {
+            return Ok(Authentication::NotPresent);
+        };
+        let Some((username, password)) = base64::decode(credentials)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|decoded| {
+                let (username, password) = decoded.split_once(':')?;
+                Some((username.to_string(), password.to_string()))
+            })
+        else {
+            return Ok(Authentication::Rejected);
+        };
+
+        let throttle = &self.database.login_throttle;
+        let peer_ip = request.peer_addr.map(|addr| addr.ip());
+        if let Some(retry_after) = throttle.retry_after(&username, peer_ip)? {
+            return Ok(Authentication::Throttled(retry_after));
+        }
+        let user = self.database.users.get(username.clone())?;
+        let authenticated = match &user {
+            Some(user) => user.verify_password(&password)?,
+            None => {
+                verify_dummy_password(&password);
+                false
+            }
+        };
+        match user {
+            Some(user) if authenticated && user.disabled => {
+                logger::info(&format!(
+                    "Refused Basic authentication for disabled user: {}",
+                    username
+                ));
+                Ok(Authentication::Rejected)
+            }
+            // Basic has no way to carry a TOTP code, so it can't stand in for
+            // the login form once the user turned on a second factor
+            Some(user) if authenticated && user.two_factor_enabled() => {
+                throttle.record_success(&username)?;
+                logger::info(&format!(
+                    "Refused Basic authentication for {}, who has two-factor authentication enabled",
+                    username
+                ));
+                Ok(Authentication::Rejected)
+            }
+            Some(user) if authenticated => {
+                throttle.record_success(&username)?;
+                Ok(Authentication::Authenticated(Principal {
+                    username: user.username,
+                    session: None,
+                    roles: user.roles,
+                    method: AuthMethod::Basic,
+                    second_factor: false,
+                }))
+            }
+            _ => {
+                if throttle.record_failure(&username, peer_ip)? {
+                    AuditEvent::failure(AuditAction::Lockout)
+                        .request(request)
+                        .username(&username)
+                        .record();
+                }
+                Ok(Authentication::Rejected)
+            }
+        }
+    }
+
+    fn challenge(&self) -> Option<String> {
+        Some(format!(r#"Basic realm="{}", charset="UTF-8""#, self.realm))
+    }
+}
+
+// `Authorization: Bearer` with an API key. The principal only carries the
+// owner's roles that are also among the key's scopes.
+pub struct BearerAuthenticator {
+    pub database: Arc<Database>,
+    pub realm: String,
+}
+
+impl BearerAuthenticator {
+    pub fn new(database: Arc<Database>) -> Self {
+        BearerAuthenticator {
+            database,
+            realm: DEFAULT_REALM.to_string(),
+        }
+    }
+}
+
+impl Authenticator for BearerAuthenticator {
+    fn authenticate(&self, request: &Request) -> Result<Authentication> {
+        let Some(token) = authorization(request, "Bearer") else {
+            return Ok(Authentication::NotPresent);
+        };
+        // Other bearer tokens, like JWTs, are for other authenticators
+        if !token.starts_with(API_KEY_PREFIX) {
+            return Ok(Authentication::NotPresent);
+        }
+        let Some(api_key) = self.database.find_api_key(token)? else {
+            return Ok(Authentication::Rejected);
+        };
+        let Some(user) = self
+            .database
+            .users
+            .get(api_key.username.clone())?
+            .filter(|user| !user.disabled)
+        else {
+            return Ok(Authentication::Rejected);
+        };
+        // Only as good as the session that created the key, and downgraded
+        // like sessions when the second factor is turned off
+        let second_factor = api_key.second_factor && user.two_factor_enabled();
+        let roles = user
+            .roles
+            .into_iter()
+            .filter(|role| api_key.scopes.contains(role))
+            .collect();
+        Ok(Authentication::Authenticated(Principal {
+            username: user.username,
+            session: None,
+            roles,
+            method: AuthMethod::ApiKey,
+            second_factor,
+        }))
+    }
+
+    fn challenge(&self) -> Option<String> {
+        Some(format!(r#"Bearer realm="{}""#, self.realm))
+    }
+}
+
+// `Authorization: Bearer` with a JWT from `Jwt::sign`. Stateless: the
+// principal is built from the claims alone, without touching the database.
+pub struct JwtAuthenticator {
+    pub jwt: Arc<Jwt>,
+    pub realm: String,
+}
+
+impl JwtAuthenticator {
+    pub fn new(jwt: Arc<Jwt>) -> Self {
+        JwtAuthenticator {
+            jwt,
+            realm: DEFAULT_REALM.to_string(),
+        }
+    }
+}
+
+impl Authenticator for JwtAuthenticator {
+    fn authenticate(&self, request: &Request) -> Result<Authentication> {
+        let Some(token) = authorization(request, "Bearer") else {
+            return Ok(Authentication::NotPresent);
+        };
+        if token.matches('.').count() != 2 {
+            return Ok(Authentication::NotPresent);
+        }
+        match self.jwt.verify(token) {
+            Ok(claims) => Ok(Authentication::Authenticated(Principal {
+                second_factor: claims.auth_methods.iter().any(|method| method == "otp"),
+                username: claims.subject,
+                session: None,
+                roles: claims.roles,
+                method: AuthMethod::Jwt,
+            })),
+            Err(e) => {
+                logger::info(&format!("Rejected JWT: {}", e));
+                Ok(Authentication::Rejected)
+            }
+        }
+    }
+
+    fn challenge(&self) -> Option<String> {
+        Some(format!(r#"Bearer realm="{}""#, self.realm))
+    }
+}
+
+// The credentials of an `Authorization: <scheme> <credentials>` header.
+fn authorization<'a>(request: &'a Request, scheme: &str) -> Option<&'a str> {
+    let (request_scheme, credentials) = request.headers.get("authorization")?.split_once(' ')?;
+    request_scheme
+        .eq_ignore_ascii_case(scheme)
+        .then(|| credentials.trim())
+}
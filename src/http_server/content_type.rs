@@ -45,6 +45,6 @@ impl FromStr for ContentType {
 }
 impl Display for ContentType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_str().to_string())
+        write!(f, "{}", self.as_str())
     }
 }
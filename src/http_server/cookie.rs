@@ -1,7 +1,37 @@
-#[derive(Clone)]
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::utils::format_http_date;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Cookie {
     pub name: String,
     pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub expires: Option<SystemTime>,
+    pub max_age: Option<Duration>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+    pub partitioned: bool,
 }
 
 impl Cookie {
@@ -10,10 +40,144 @@ impl Cookie {
         Self {
             name: cookie_name,
             value,
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        }
+    }
+
+    // A cookie that makes the browser delete `name`. Path and domain have to
+    // match the ones the cookie was set with.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Cookie::new(name, String::new()).into_removal()
+    }
+
+    pub fn into_removal(mut self) -> Self {
+        self.value = String::new();
+        self.max_age = Some(Duration::ZERO);
+        self.expires = Some(UNIX_EPOCH);
+        self
+    }
+
+    pub fn path(mut self, path: impl ToString) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl ToString) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    // The Set-Cookie value. Browsers drop `SameSite=None` and `Partitioned`
+    // cookies that aren't `Secure`, so those imply it.
+    pub fn cookie_string(&self) -> String {
+        let mut attributes = vec![format!("{}={}", self.name, self.value)];
+        if let Some(path) = &self.path {
+            attributes.push(format!("Path={}", path));
+        }
+        if let Some(domain) = &self.domain {
+            attributes.push(format!("Domain={}", domain));
+        }
+        if let Some(expires) = self.expires {
+            attributes.push(format!("Expires={}", format_http_date(expires)));
         }
+        if let Some(max_age) = self.max_age {
+            attributes.push(format!("Max-Age={}", max_age.as_secs()));
+        }
+        if self.secure || self.partitioned || self.same_site == Some(SameSite::None) {
+            attributes.push("Secure".to_string());
+        }
+        if self.http_only {
+            attributes.push("HttpOnly".to_string());
+        }
+        if let Some(same_site) = self.same_site {
+            attributes.push(format!("SameSite={}", same_site));
+        }
+        if self.partitioned {
+            attributes.push("Partitioned".to_string());
+        }
+        attributes.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_every_attribute() {
+        let cookie = Cookie::new("session", "abc".to_string())
+            .path("/")
+            .domain("example.com")
+            .expires(UNIX_EPOCH + Duration::from_secs(86400))
+            .max_age(Duration::from_secs(3600))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .partitioned(true);
+        assert_eq!(
+            cookie.cookie_string(),
+            "session=abc; Path=/; Domain=example.com; Expires=Fri, 02 Jan 1970 00:00:00 GMT; \
+             Max-Age=3600; Secure; HttpOnly; SameSite=Strict; Partitioned"
+        );
+        assert_eq!(
+            Cookie::new("plain", "1".to_string()).cookie_string(),
+            "plain=1"
+        );
+    }
+
+    #[test]
+    fn same_site_none_and_partitioned_imply_secure() {
+        let none = Cookie::new("a", "1".to_string()).same_site(SameSite::None);
+        assert_eq!(none.cookie_string(), "a=1; Secure; SameSite=None");
+        let partitioned = Cookie::new("a", "1".to_string()).partitioned(true);
+        assert_eq!(partitioned.cookie_string(), "a=1; Secure; Partitioned");
     }
 
-    pub fn cookie_string(self) -> String {
-        format!("{}={}", self.name, self.value)
+    #[test]
+    fn removal_expires_the_cookie() {
+        let removal = Cookie::new("session", "abc".to_string())
+            .path("/account")
+            .into_removal();
+        assert_eq!(
+            removal.cookie_string(),
+            "session=; Path=/account; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0"
+        );
     }
 }
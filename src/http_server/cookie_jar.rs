@@ -0,0 +1,147 @@
+use crate::crypto::{chacha20poly1305, constant_time_eq, hmac_sha256};
+use crate::error::Result;
+use crate::http_server::{Cookie, Request};
+use crate::utils::{base64, random};
+
+const MIN_SECRET_LEN: usize = 32;
+
+// Signing and encryption keys derived from one server secret.
+#[derive(Clone)]
+pub struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+impl Key {
+    pub fn from_secret(secret: &[u8]) -> Result<Key> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!("Secret key must be at least {} bytes", MIN_SECRET_LEN).into());
+        }
+        Ok(Key {
+            signing: hmac_sha256(secret, b"cookie-signing"),
+            encryption: hmac_sha256(secret, b"cookie-encryption"),
+        })
+    }
+
+    // A random key, for servers that don't configure a secret. Cookies it
+    // issued stop verifying when the process restarts.
+    pub fn generate() -> Result<Key> {
+        let mut secret = [0u8; 32];
+        random::fill(&mut secret)?;
+        Key::from_secret(&secret)
+    }
+}
+
+// The current key, used for new cookies, followed by retired keys that are
+// still accepted so secrets can be rotated without invalidating every cookie.
+#[derive(Clone)]
+pub struct CookieKeys {
+    keys: Vec<Key>,
+}
+
+impl CookieKeys {
+    pub fn new(current: Key) -> Self {
+        CookieKeys {
+            keys: vec![current],
+        }
+    }
+
+    pub fn previous(mut self, key: Key) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    pub fn signed(&self) -> SignedJar<'_> {
+        SignedJar { keys: &self.keys }
+    }
+
+    pub fn private(&self) -> PrivateJar<'_> {
+        PrivateJar { keys: &self.keys }
+    }
+}
+
+// Cookies readable by the client but not modifiable: the value is prefixed
+// with an HMAC-SHA256 over the cookie name and value.
+pub struct SignedJar<'a> {
+    keys: &'a [Key],
+}
+
+impl SignedJar<'_> {
+    pub fn sign(&self, mut cookie: Cookie) -> Cookie {
+        let mac = signature(&self.keys[0], &cookie.name, &cookie.value);
+        cookie.value = format!("{}.{}", base64::encode_url(&mac), cookie.value);
+        cookie
+    }
+
+    pub fn verify(&self, mut cookie: Cookie) -> Option<Cookie> {
+        let (mac, value) = cookie.value.split_once('.')?;
+        let mac = base64::decode_url(mac).ok()?;
+        let valid = self
+            .keys
+            .iter()
+            .any(|key| constant_time_eq(&signature(key, &cookie.name, value), &mac));
+        if !valid {
+            return None;
+        }
+        cookie.value = value.to_string();
+        Some(cookie)
+    }
+
+    pub fn get(&self, request: &Request, name: &str) -> Option<Cookie> {
+        request
+            .cookies()
+            .into_iter()
+            .filter(|cookie| cookie.name == name)
+            .find_map(|cookie| self.verify(cookie))
+    }
+}
+
+fn signature(key: &Key, name: &str, value: &str) -> [u8; 32] {
+    hmac_sha256(&key.signing, format!("{}={}", name, value).as_bytes())
+}
+
+// Cookies the client can neither read nor modify, sealed with
+// ChaCha20-Poly1305. The cookie name is authenticated as associated data so a
+// value can't be moved to another cookie.
+pub struct PrivateJar<'a> {
+    keys: &'a [Key],
+}
+
+impl PrivateJar<'_> {
+    pub fn encrypt(&self, mut cookie: Cookie) -> Result<Cookie> {
+        let mut nonce = [0u8; chacha20poly1305::NONCE_LEN];
+        random::fill(&mut nonce)?;
+        let sealed = chacha20poly1305::seal(
+            &self.keys[0].encryption,
+            &nonce,
+            cookie.name.as_bytes(),
+            cookie.value.as_bytes(),
+        );
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        cookie.value = base64::encode_url(&payload);
+        Ok(cookie)
+    }
+
+    pub fn decrypt(&self, mut cookie: Cookie) -> Option<Cookie> {
+        let payload = base64::decode_url(&cookie.value).ok()?;
+        if payload.len() < chacha20poly1305::NONCE_LEN {
+            return None;
+        }
+        let (nonce, sealed) = payload.split_at(chacha20poly1305::NONCE_LEN);
+        let nonce: [u8; chacha20poly1305::NONCE_LEN] = nonce.try_into().ok()?;
+        let plaintext = self.keys.iter().find_map(|key| {
+            chacha20poly1305::open(&key.encryption, &nonce, cookie.name.as_bytes(), sealed).ok()
+        })?;
+        cookie.value = String::from_utf8(plaintext).ok()?;
+        Some(cookie)
+    }
+
+    pub fn get(&self, request: &Request, name: &str) -> Option<Cookie> {
+        request
+            .cookies()
+            .into_iter()
+            .filter(|cookie| cookie.name == name)
+            .find_map(|cookie| self.decrypt(cookie))
+    }
+}
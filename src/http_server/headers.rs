@@ -0,0 +1,119 @@
+// Header fields in the order they were received or added. Names compare
+// case-insensitively, values are kept exactly as given and a name may appear
+// more than once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Headers::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    // All values of a list-based field joined into one, as RFC 9110 allows.
+    pub fn get_combined(&self, name: &str) -> Option<String> {
+        let values = self.get_all(name);
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // Replaces every existing value of `name`.
+    pub fn insert(&mut self, name: impl ToString, value: impl ToString) {
+        let name = name.to_string();
+        self.remove(&name);
+        self.entries.push((name, value.to_string()));
+    }
+
+    pub fn append(&mut self, name: impl ToString, value: impl ToString) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.entries.len();
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.entries.len() != before
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Continues the last field's value with an obsolete line fold.
+    pub(crate) fn extend_last(&mut self, continuation: &str) -> bool {
+        match self.entries.last_mut() {
+            Some((_, value)) => {
+                if !value.is_empty() {
+                    value.push(' ');
+                }
+                value.push_str(continuation);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let mut headers = Headers::new();
+        headers.append("Content-Type", "text/HTML");
+        assert_eq!(headers.get("content-type"), Some("text/HTML"));
+        assert!(headers.contains("CONTENT-TYPE"));
+        assert!(headers.remove("content-TYPE"));
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn keeps_repeated_fields_in_order() {
+        let mut headers = Headers::new();
+        headers.append("Accept", "text/html");
+        headers.append("X-Other", "x");
+        headers.append("accept", "application/json");
+        assert_eq!(headers.get("accept"), Some("text/html"));
+        assert_eq!(headers.get_all("Accept"), ["text/html", "application/json"]);
+        assert_eq!(
+            headers.get_combined("accept").as_deref(),
+            Some("text/html, application/json")
+        );
+        assert_eq!(headers.get_combined("missing"), None);
+
+        headers.insert("ACCEPT", "*/*");
+        assert_eq!(headers.get_all("accept"), ["*/*"]);
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["X-Other", "ACCEPT"]);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::error::Result;
+use crate::http_server::{
+    too_many_requests_response, AuditAction, AuditEvent, Authentication, Authenticator, Middleware, Next, Request, Response,
+    ResponseBuilder,
+};
+
+// The `WWW-Authenticate` challenges of the configured authenticators, kept on
+// the request so a later 401 can tell the client how to authenticate.
+#[derive(Debug, Clone, Default)]
+pub struct AuthChallenges(pub Vec<String>);
+
+// Tries each authenticator in turn and attaches the first `Principal` found.
+// Requests without credentials continue anonymously, for `RequireAuth` and
+// `RequireRole` to judge; wrong credentials get 401, throttled ones 429.
+#[derive(Default)]
+pub struct Authenticate {
+    authenticators: Vec<Box<dyn Authenticator>>,
+}
+
+impl Authenticate {
+    pub fn new() -> Self {
+        Authenticate::default()
+    }
+
+    pub fn authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.authenticators.push(Box::new(authenticator));
+        self
+    }
+}
+
+impl Middleware for Authenticate {
+    fn handle(&self, mut request: Request, next: Next) -> Result<Response> {
+        let mut challenges = AuthChallenges::default();
+        for challenge in self.authenticators.iter().filter_map(|a| a.challenge()) {
+            // API keys and JWTs both answer the same Bearer challenge
+            if !challenges.0.contains(&challenge) {
+                challenges.0.push(challenge);
+            }
+        }
+        for authenticator in &self.authenticators {
+            match authenticator.authenticate(&request)? {
+                Authentication::NotPresent => continue,
+                Authentication::Authenticated(principal) => {
+                    request.extensions.insert(principal);
+                    break;
+                }
+                Authentication::Rejected => {
+                    AuditEvent::failure(AuditAction::AuthenticationFailed)
+                        .request(&request)
+                        .detail(authorization_scheme(&request))
+                        .record();
+                    return Ok(unauthorized_response(&challenges));
+                }
+                Authentication::Throttled(retry_after) => {
+                    AuditEvent::failure(AuditAction::Throttled)
+                        .request(&request)
+                        .detail(authorization_scheme(&request))
+                        .record();
+                    return Ok(too_many_requests_response(retry_after));
+                }
+            }
+        }
+        request.extensions.insert(challenges);
+        next.run(request)
+    }
+}
+
+pub fn unauthorized_response(challenges: &AuthChallenges) -> Response {
+    let mut response = ResponseBuilder::new()
+        .status_code(401)
+        .reason_phrase("Unauthorized".to_string())
+        .build();
+    for challenge in &challenges.0 {
+        response.headers.append("WWW-Authenticate", challenge);
+    }
+    response
+}
+
+// Which kind of credentials were offered, for the audit trail.
+fn authorization_scheme(request: &Request) -> String {
+    let scheme = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.split_whitespace().next())
+        .unwrap_or("unknown");
+    format!("{} authentication", scheme)
+}
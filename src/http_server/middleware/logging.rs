@@ -0,0 +1,26 @@
+use crate::error::Result;
+use crate::http_server::{Middleware, Next, Request, Response};
+use crate::utils::logger;
+
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn handle(&self, request: Request, next: Next) -> Result<Response> {
+        let method = request.method.clone();
+        let path = request.path.clone();
+        let request_id = request.request_id().unwrap_or("-").to_string();
+        match next.run(request) {
+            Ok(response) => {
+                logger::info(&format!(
+                    "{} {} {} {}",
+                    request_id, method, path, response.status_code
+                ));
+                Ok(response)
+            }
+            Err(e) => {
+                logger::error(&format!("{} {} {} failed: {}", request_id, method, path, e));
+                Err(e)
+            }
+        }
+    }
+}
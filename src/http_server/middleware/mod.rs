@@ -0,0 +1,60 @@
+pub mod authenticate;
+pub mod csrf;
+pub mod logging;
+pub mod session_auth;
+pub mod timing;
+
+pub use authenticate::{unauthorized_response, AuthChallenges, Authenticate};
+pub use csrf::{Csrf, CsrfToken, CSRF_FIELD, CSRF_HEADER};
+pub use logging::RequestLogger;
+pub use session_auth::{RequireAuth, RequireRole, RequireSecondFactor, SessionAuth};
+pub use timing::Timing;
+
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::http_server::{Request, Response, RouteHandler};
+
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: Request, next: Next) -> Result<Response>;
+}
+
+// The rest of the pipeline. A middleware either calls `run` to pass the request
+// on, or returns its own response to short-circuit.
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    endpoint: &'a dyn Fn(Request) -> Result<Response>,
+}
+
+impl<'a> Next<'a> {
+    pub fn new(
+        middleware: &'a [Arc<dyn Middleware>],
+        endpoint: &'a dyn Fn(Request) -> Result<Response>,
+    ) -> Self {
+        Next {
+            middleware,
+            endpoint,
+        }
+    }
+
+    pub fn run(self, request: Request) -> Result<Response> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(request, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(request),
+        }
+    }
+}
+
+// A handler wrapped in its own middleware stack, used for route and group
+// scoped middleware.
+pub struct WithMiddleware {
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub handler: Arc<dyn RouteHandler>,
+}
+
+impl RouteHandler for WithMiddleware {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let endpoint = |request: Request| self.handler.handle(request);
+        Next::new(&self.middleware, &endpoint).run(request)
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::error::Result;
+use crate::http_server::{
+    authenticate_session, default_forbidden_response, AuditAction, AuditEvent, unauthorized_response, AuthChallenges,
+    AuthResult, ContentType, Middleware, Next, Request, Response, ResponseBuilder,
+};
+
+// Authenticates the request's session cookie and attaches the `Principal` to
+// the request. By default everything without a valid session is redirected
+// to `redirect`, or answered with 401 when no redirect is configured. An
+// `optional()` SessionAuth lets those requests through anonymously, leaving
+// the decision to `RequireAuth` on the routes that need it.
+pub struct SessionAuth {
+    pub database: Arc<Database>,
+    pub redirect: Option<String>,
+    pub optional: bool,
+}
+
+impl SessionAuth {
+    pub fn new(database: Arc<Database>) -> Self {
+        SessionAuth {
+            database,
+            redirect: None,
+            optional: false,
+        }
+    }
+
+    pub fn redirect_to(mut self, location: impl ToString) -> Self {
+        self.redirect = Some(location.to_string());
+        self
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+}
+
+impl Middleware for SessionAuth {
+    fn handle(&self, mut request: Request, next: Next) -> Result<Response> {
+        match authenticate_session(&self.database, &request)? {
+            AuthResult::Authenticated(principal) => {
+                request.extensions.insert(principal);
+                next.run(request)
+            }
+            AuthResult::SessionNotPresent
+            | AuthResult::SessionInvalid
+            | AuthResult::SessionExpired
+            | AuthResult::SecondFactorPending(_) => {
+                if self.optional {
+                    next.run(request)
+                } else {
+                    Ok(unauthenticated_response(&request, self.redirect.as_deref()))
+                }
+            }
+        }
+    }
+}
+
+// Lets a request through only when an earlier middleware, such as an optional
+// `SessionAuth`, attached a `Principal` to it.
+#[derive(Default)]
+pub struct RequireAuth {
+    pub redirect: Option<String>,
+}
+
+impl RequireAuth {
+    pub fn new() -> Self {
+        RequireAuth::default()
+    }
+
+    pub fn redirect_to(mut self, location: impl ToString) -> Self {
+        self.redirect = Some(location.to_string());
+        self
+    }
+}
+
+impl Middleware for RequireAuth {
+    fn handle(&self, request: Request, next: Next) -> Result<Response> {
+        if request.principal().is_some() {
+            next.run(request)
+        } else {
+            Ok(unauthenticated_response(&request, self.redirect.as_deref()))
+        }
+    }
+}
+
+// Lets a request through only when its `Principal` has at least one of
+// `roles`. Anonymous requests get 401 like with `RequireAuth`, authenticated
+// ones without the role get 403 and the forbidden page.
+pub struct RequireRole {
+    pub roles: Vec<String>,
+    pub forbidden: Response,
+}
+
+impl RequireRole {
+    pub fn new(role: impl ToString) -> Self {
+        RequireRole::any_of(&[role])
+    }
+
+    pub fn any_of(roles: &[impl ToString]) -> Self {
+        RequireRole {
+            roles: roles.iter().map(ToString::to_string).collect(),
+            forbidden: default_forbidden_response(),
+        }
+    }
+
+    pub fn forbidden_response(mut self, response: Response) -> Self {
+        self.forbidden = response;
+        self
+    }
+}
+
+impl Middleware for RequireRole {
+    fn handle(&self, request: Request, next: Next) -> Result<Response> {
+        match request.principal() {
+            None => Ok(unauthenticated_response(&request, None)),
+            Some(principal) if self.roles.iter().any(|role| principal.has_role(role)) => {
+                next.run(request)
+            }
+            Some(_) => {
+                AuditEvent::failure(AuditAction::AccessDenied)
+                    .request(&request)
+                    .detail(format!(
+                        "{} {} requires one of the roles {}",
+                        request.method,
+                        request.path,
+                        self.roles.join(", ")
+                    ))
+                    .record();
+                Ok(self.forbidden.clone())
+            }
+        }
+    }
+}
+
+// Lets a request through only when its `Principal` proved a second factor,
+// e.g. a session that entered a TOTP code at login. Anonymous requests get
+// 401, others 403 telling the user to set one up.
+pub struct RequireSecondFactor {
+    pub forbidden: Response,
+}
+
+impl Default for RequireSecondFactor {
+    fn default() -> Self {
+        RequireSecondFactor {
+            forbidden: ResponseBuilder::new()
+                .status_code(403)
+                .reason_phrase("Forbidden".to_string())
+                .content_type(ContentType::PlainTest)
+                .body_string(
+                    "Two-factor authentication required. Set it up at /account/2fa/enroll."
+                        .to_string(),
+                )
+                .build(),
+        }
+    }
+}
+
+impl RequireSecondFactor {
+    pub fn new() -> Self {
+        RequireSecondFactor::default()
+    }
+
+    pub fn forbidden_response(mut self, response: Response) -> Self {
+        self.forbidden = response;
+        self
+    }
+}
+
+impl Middleware for RequireSecondFactor {
+    fn handle(&self, request: Request, next: Next) -> Result<Response> {
+        match request.principal() {
+            None => Ok(unauthenticated_response(&request, None)),
+            Some(principal) if principal.second_factor => next.run(request),
+            Some(_) => {
+                AuditEvent::failure(AuditAction::AccessDenied)
+                    .request(&request)
+                    .detail(format!(
+                        "{} {} requires a second factor",
+                        request.method, request.path
+                    ))
+                    .record();
+                Ok(self.forbidden.clone())
+            }
+        }
+    }
+}
+
+// A redirect when one is configured, otherwise 401 with the challenges of
+// whatever authenticators `Authenticate` ran.
+fn unauthenticated_response(request: &Request, redirect: Option<&str>) -> Response {
+    match redirect {
+        Some(location) => ResponseBuilder::new().temp_redirect(location).build(),
+        None => unauthorized_response(
+            request
+                .extensions
+                .get::<AuthChallenges>()
+                .unwrap_or(&AuthChallenges::default()),
+        ),
+    }
+}
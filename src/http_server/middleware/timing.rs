@@ -0,0 +1,19 @@
+use std::time::Instant;
+
+use crate::error::Result;
+use crate::http_server::{Middleware, Next, Request, Response};
+
+// Reports how long the rest of the pipeline took in a Server-Timing header.
+pub struct Timing;
+
+impl Middleware for Timing {
+    fn handle(&self, request: Request, next: Next) -> Result<Response> {
+        let start = Instant::now();
+        let mut response = next.run(request)?;
+        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+        response
+            .headers
+            .insert("Server-Timing", format!("app;dur={:.3}", elapsed_ms));
+        Ok(response)
+    }
+}
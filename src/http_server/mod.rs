@@ -1,17 +1,35 @@
+pub mod audit;
 pub mod auth;
+pub mod authenticator;
 pub mod content_type;
 pub mod cookie;
+pub mod cookie_jar;
+pub mod extensions;
+pub mod headers;
+pub mod middleware;
 pub mod request;
 pub mod response;
+pub mod route;
 pub mod router;
 pub mod server;
 pub mod server_builder;
+pub mod shutdown;
+pub mod url_encoding;
 
+pub use audit::*;
 pub use auth::*;
+pub use authenticator::*;
 pub use content_type::*;
-pub use cookie::Cookie;
+pub use cookie::{Cookie, SameSite};
+pub use cookie_jar::*;
+pub use extensions::Extensions;
+pub use headers::Headers;
+pub use middleware::*;
 pub use request::*;
 pub use response::*;
+pub use route::*;
 pub use router::*;
 pub use server::*;
 pub use server_builder::*;
+pub use shutdown::ShutdownHandle;
+pub use url_encoding::*;
@@ -1,18 +1,51 @@
 
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, Lines},
-    net::TcpStream,
+    fmt::Display,
+    io::{BufRead, BufReader, Read},
+    net::{SocketAddr, TcpStream},
     str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
 };
 
 use crate::error::Result;
-use crate::http_server::{ContentType, Cookie, Route};
+use crate::utils::random;
+use crate::http_server::{
+    parse_form, percent_decode, ContentType, Cookie, CookieKeys, CsrfToken, Extensions, Headers,
+    Principal, Route,
+};
+
+// Identifies one request in the logs, the audit trail and the response's
+// `X-Request-Id` header. A per-process random prefix and a counter, so ids
+// are unique across restarts without a syscall per request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn next() -> RequestId {
+        static PREFIX: OnceLock<u32> = OnceLock::new();
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        let prefix = PREFIX.get_or_init(|| random::u64().map_or(std::process::id(), |n| n as u32));
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        RequestId(format!("{:08x}-{:06}", prefix, count))
+    }
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum HttpMethod {
     GET,
+    HEAD,
     POST,
+    PUT,
+    DELETE,
+    CONNECT,
+    OPTIONS,
+    TRACE,
+    PATCH,
+    Other(String),
 }
 impl FromStr for HttpMethod {
     type Err = String;
@@ -20,51 +53,168 @@ impl FromStr for HttpMethod {
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s.to_uppercase().as_str() {
             "GET" => Ok(HttpMethod::GET),
+            "HEAD" => Ok(HttpMethod::HEAD),
             "POST" => Ok(HttpMethod::POST),
+            "PUT" => Ok(HttpMethod::PUT),
+            "DELETE" => Ok(HttpMethod::DELETE),
+            "CONNECT" => Ok(HttpMethod::CONNECT),
+            "OPTIONS" => Ok(HttpMethod::OPTIONS),
+            "TRACE" => Ok(HttpMethod::TRACE),
+            "PATCH" => Ok(HttpMethod::PATCH),
+            _ if !s.is_empty() && s.bytes().all(is_token_char) => {
+                Ok(HttpMethod::Other(s.to_string()))
+            }
             _ => Err(format!("Invalid Http method {}", s)),
         }
     }
 }
 
+// tchar from RFC 9110, section 5.6.2
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+impl Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let method = match self {
+            HttpMethod::GET => "GET",
+            HttpMethod::HEAD => "HEAD",
+            HttpMethod::POST => "POST",
+            HttpMethod::PUT => "PUT",
+            HttpMethod::DELETE => "DELETE",
+            HttpMethod::CONNECT => "CONNECT",
+            HttpMethod::OPTIONS => "OPTIONS",
+            HttpMethod::TRACE => "TRACE",
+            HttpMethod::PATCH => "PATCH",
+            HttpMethod::Other(method) => method,
+        };
+        write!(f, "{}", method)
+    }
+}
+
 impl HttpMethod {
     pub fn get(path: &str) -> Route {
         Route::new()
             .http_method(HttpMethod::GET)
             .path(path.to_string())
     }
+    pub fn head(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::HEAD)
+            .path(path.to_string())
+    }
     pub fn post(path: &str) -> Route {
         Route::new()
             .http_method(HttpMethod::POST)
             .path(path.to_string())
     }
+    pub fn put(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::PUT)
+            .path(path.to_string())
+    }
+    pub fn delete(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::DELETE)
+            .path(path.to_string())
+    }
+    pub fn connect(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::CONNECT)
+            .path(path.to_string())
+    }
+    pub fn options(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::OPTIONS)
+            .path(path.to_string())
+    }
+    pub fn trace(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::TRACE)
+            .path(path.to_string())
+    }
+    pub fn patch(path: &str) -> Route {
+        Route::new()
+            .http_method(HttpMethod::PATCH)
+            .path(path.to_string())
+    }
+    pub fn one_of(methods: &[HttpMethod], path: &str) -> Route {
+        Route::new()
+            .http_methods(methods)
+            .path(path.to_string())
+    }
+    pub fn any(path: &str) -> Route {
+        Route::new().any_method().path(path.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryParams(Vec<(String, String)>);
+
+impl QueryParams {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Request {
     pub method: HttpMethod,
     pub path: String,
+    pub query: Option<String>,
     pub http_version: String,
-    pub headers: HashMap<String, String>,
+    pub headers: Headers,
     pub body: Vec<u8>,
+    pub params: HashMap<String, String>,
+    pub extensions: Extensions,
+    // The client's address, `None` when the request didn't come off a socket.
+    pub peer_addr: Option<SocketAddr>,
 }
 
 impl Request {
     pub fn from_reader(reader: &mut BufReader<TcpStream>) -> Result<Request> {
-        let mut lines = reader.lines();
-        let request_line = read_request_line(&mut lines)?;
-        let headers = parse_headers(&mut lines)?;
+        let request_line = read_request_line(reader)?;
+        let headers = parse_headers(reader)?;
+        // Chunked bodies aren't supported. Ignoring the header would read the
+        // chunks as the next request on a persistent connection.
+        if headers.contains("transfer-encoding") {
+            return Err("Transfer-Encoding is not supported".into());
+        }
         // Read body
         let mut body = Vec::new();
 
-        if let Some(len) = headers.get("content-length") {
+        if let Some(len) = content_length(&headers)? {
             let len: usize = len
                 .parse::<usize>()
-                .map_err(|_| "Error parsing content length".to_string())?;
+                .ok()
+                .filter(|_| len.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or("Error parsing content length")?;
+            if len > MAX_BODY_LENGTH {
+                return Err(format!("Request body of {} bytes is too large", len).into());
+            }
             body.reserve(len);
             while body.len() < len {
                 let buffer = reader
                     .fill_buf()
                     .map_err(|_| "Error reading request body".to_string())?;
+                if buffer.is_empty() {
+                    return Err("Connection closed while reading request body".into());
+                }
                 let bytes_to_read = std::cmp::min(buffer.len(), len - body.len());
                 body.extend_from_slice(&buffer[..bytes_to_read]);
                 reader.consume(bytes_to_read);
@@ -73,11 +223,16 @@ impl Request {
 
         // parse request_line for method, path and http_version
         let parts: Vec<&str> = request_line.split_whitespace().collect();
-        let (method_string, path, http_version) = (
+        let (method_string, target, http_version) = (
             parts[0].to_string(),
-            parts[1].to_string(),
+            parts[1],
             parts[2].to_string(),
         );
+        let target = target.split('#').next().unwrap_or_default();
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
 
         let method = HttpMethod::from_str(&method_string)
             .map_err(|_| "Invalid HTTP Method".to_string())?;
@@ -85,31 +240,55 @@ impl Request {
         Ok(Request {
             method,
             path,
+            query,
             http_version,
             headers,
             body,
+            params: HashMap::new(),
+            extensions: Extensions::new(),
+            peer_addr: reader.get_ref().peer_addr().ok(),
         })
     }
 
+    // Decoded path segments. Decoding happens per segment so an encoded `/`
+    // (%2F) stays inside its segment.
+    pub fn path_segments(&self) -> Vec<String> {
+        self.path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(percent_decode)
+            .collect()
+    }
+
+    pub fn query(&self) -> QueryParams {
+        QueryParams(self.query.as_deref().map(parse_form).unwrap_or_default())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query().get(name).map(String::from)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    pub fn param_as<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.param(name).and_then(|value| value.parse().ok())
+    }
+
     pub fn form_urlencoded(&self) -> Option<HashMap<String, String>> {
         match self.content_type() {
             Some(ContentType::FormUrlEncoded) => {
-                String::from_utf8(self.body.clone()).ok().map(|body_str| {
-                    body_str
-                        .split('&')
-                        .filter_map(|part| {
-                            let mut split = part.splitn(2, '=');
-                            Some((split.next()?.to_string(), split.next()?.to_string()))
-                        })
-                        .collect::<HashMap<String, String>>()
-                })
+                String::from_utf8(self.body.clone())
+                    .ok()
+                    .map(|body_str| parse_form(&body_str).into_iter().collect())
             }
             _ => None,
         }
     }
     pub fn cookies(&self) -> Vec<Cookie> {
         let mut cookies = Vec::new();
-        if let Some(cookie_header) = self.headers.get("cookie") {
+        for cookie_header in self.headers.get_all("cookie") {
             for cookie_str in cookie_header.split(';') {
                 let parts: Vec<&str> = cookie_str.splitn(2, '=').collect();
                 if parts.len() == 2 {
@@ -122,17 +301,78 @@ impl Request {
         cookies
     }
 
+    // The keys the server signs and encrypts cookies with, see `CookieKeys`.
+    pub fn cookie_keys(&self) -> Option<&CookieKeys> {
+        self.extensions.get::<Arc<CookieKeys>>().map(Arc::as_ref)
+    }
+
+    // Who made the request, when authentication middleware recognised them.
+    pub fn principal(&self) -> Option<&Principal> {
+        self.extensions.get::<Principal>()
+    }
+
+    // The id the server gave this request, see `RequestId`.
+    pub fn request_id(&self) -> Option<&str> {
+        self.extensions.get::<RequestId>().map(|id| id.0.as_str())
+    }
+
+    // The CSRF token forms on the page must send back, when `Csrf` runs.
+    pub fn csrf_token(&self) -> Option<&CsrfToken> {
+        self.extensions.get::<CsrfToken>()
+    }
+
+    // HTTP/1.1 connections are persistent unless the client asks to close,
+    // HTTP/1.0 ones only when the client explicitly opts in.
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.headers
+                .get_all("connection")
+                .iter()
+                .flat_map(|value| value.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+        if self.http_version.eq_ignore_ascii_case("HTTP/1.0") {
+            has_token("keep-alive")
+        } else {
+            !has_token("close")
+        }
+    }
+
     pub fn content_type(&self) -> Option<ContentType> {
         self.headers
             .get("content-type")
-            .and_then(|s| ContentType::from_str(s).ok())
+            .and_then(|s| ContentType::from_str(s.split(';').next()?.trim()).ok())
+    }
+}
+
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY_LENGTH: usize = 1024 * 1024;
+
+// Reads one CRLF (or bare LF) terminated line. Bytes that aren't valid UTF-8
+// are taken as Latin-1 (obs-text) so nothing is lost.
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LENGTH as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .map_err(|error| format!("Unable to read line: {}", error))?;
+    if line.last() != Some(&b'\n') {
+        return Err("Line too long or connection closed".into());
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match String::from_utf8(line) {
+        Ok(line) => Ok(line),
+        Err(e) => Ok(e.into_bytes().iter().map(|&b| b as char).collect()),
     }
 }
 
-fn read_request_line(lines: &mut Lines<&mut BufReader<TcpStream>>) -> Result<String> {
-    let request_line_result = lines.next().ok_or_else(|| "No request line")?;
-    let request_line_str = request_line_result
-        .map_err(|error| format!("Unable to get request string: {}", error))?;
+fn read_request_line(reader: &mut impl BufRead) -> Result<String> {
+    let request_line_str = read_line(reader)?;
     let parts: Vec<&str> = request_line_str.split_whitespace().collect();
     if parts.len() != 3 {
         return Err(format!(
@@ -143,25 +383,221 @@ fn read_request_line(lines: &mut Lines<&mut BufReader<TcpStream>>) -> Result<Str
     Ok(request_line_str)
 }
 
-fn parse_headers(
-    lines: &mut Lines<&mut BufReader<TcpStream>>,
-) -> Result<HashMap<String, String>> {
-    let mut headers: HashMap<String, String> = HashMap::new();
+// Field syntax from RFC 9112, section 5. Obsolete line folding is replaced by
+// a single space, whitespace before the colon is rejected.
+fn parse_headers(reader: &mut impl BufRead) -> Result<Headers> {
+    let mut headers = Headers::new();
     loop {
-        let line = lines
-            .next()
-            .ok_or_else(||"Failed to read line")??;
+        let line = read_line(reader)?;
         if line.is_empty() {
             break;
         }
-        let parts: Vec<&str> = line.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            return Err("Invalid header format".into());
+        if line.starts_with([' ', '\t']) {
+            if !headers.extend_last(line.trim()) {
+                return Err("Header continuation without a header".into());
+            }
+            continue;
         }
-
-        let name = parts[0].trim().to_string().to_lowercase();
-        let value = parts[1].trim().to_string().to_lowercase();
-        headers.insert(name, value);
+        if headers.len() >= MAX_HEADERS {
+            return Err("Too many headers".into());
+        }
+        let (name, value) = line.split_once(':').ok_or("Invalid header format")?;
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(format!("Invalid header name {:?}", name).into());
+        }
+        headers.append(name, value.trim_matches([' ', '\t']));
     }
     Ok(headers)
 }
+
+fn content_length(headers: &Headers) -> Result<Option<String>> {
+    let values = headers.get_all("content-length");
+    match values.split_first() {
+        Some((first, rest)) if rest.iter().any(|value| value != first) => {
+            Err("Conflicting Content-Length headers".into())
+        }
+        Some((first, _)) => Ok(Some(first.to_string())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, net::TcpListener};
+
+    // Feeds `raw` to `Request::from_reader` through a local socket.
+    fn parse(raw: &[u8]) -> Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let mut client = TcpStream::connect(listener.local_addr()?)?;
+        client.write_all(raw)?;
+        client.shutdown(std::net::Shutdown::Write)?;
+        let (stream, _) = listener.accept()?;
+        Request::from_reader(&mut BufReader::new(stream))
+    }
+
+    #[test]
+    fn splits_the_target_into_path_and_query() {
+        let request =
+            parse(b"GET /a%2Fb/c%20d?tab=2&tag=x+y&tag=%26#top HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.path, "/a%2Fb/c%20d");
+        assert_eq!(request.path_segments(), ["a/b", "c d"]);
+        assert_eq!(request.query.as_deref(), Some("tab=2&tag=x+y&tag=%26"));
+        assert_eq!(request.query_param("tab").as_deref(), Some("2"));
+        assert_eq!(request.query().get("tag"), Some("x y"));
+        assert_eq!(request.query().get_all("tag"), ["x y", "&"]);
+        assert_eq!(request.query_param("missing"), None);
+
+        let request = parse(b"GET /plain HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.query, None);
+        assert!(request.query().iter().next().is_none());
+    }
+
+    #[test]
+    fn decodes_form_bodies() {
+        let request = parse(
+            b"POST /login HTTP/1.1\r\n\
+              Content-Type: application/x-www-form-urlencoded; charset=UTF-8\r\n\
+              Content-Length: 37\r\n\
+              \r\n\
+              username=a+b&password=p%26ss%25w%3Drd",
+        )
+        .unwrap();
+        let form = request.form_urlencoded().unwrap();
+        assert_eq!(form["username"], "a b");
+        assert_eq!(form["password"], "p&ss%w=rd");
+    }
+
+    #[test]
+    fn reads_the_body_by_content_length() {
+        let request =
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello, and more").unwrap();
+        assert_eq!(request.body, b"hello");
+
+        // Repeated with the same value is the same as once
+        let request =
+            parse(b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nhi").unwrap();
+        assert_eq!(request.body, b"hi");
+    }
+
+    #[test]
+    fn rejects_bad_content_lengths() {
+        let invalid: [&[u8]; 6] = [
+            b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: 3, 3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: +3\r\n\r\nabc",
+            b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+        ];
+        for raw in invalid {
+            assert!(parse(raw).is_err(), "{}", String::from_utf8_lossy(raw));
+        }
+    }
+
+    #[test]
+    fn rejects_transfer_encoding() {
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n";
+        assert!(parse(raw).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_request_lines() {
+        let invalid: [&[u8]; 4] = [
+            b"GET /\r\n\r\n",
+            b"GET / HTTP/1.1 extra\r\n\r\n",
+            b"\r\n\r\n",
+            b"GET / HTTP/1.1",
+        ];
+        for raw in invalid {
+            assert!(parse(raw).is_err(), "{}", String::from_utf8_lossy(raw));
+        }
+    }
+
+    fn parse_fields(raw: &str) -> Result<Headers> {
+        parse_headers(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn keeps_header_values_and_repeats() {
+        let headers = parse_fields(
+            "Host: Example.COM\r\nX-Token:  AbC=dEf \r\nCookie: a=1\r\ncookie: b=2\r\n\r\nbody",
+        )
+        .unwrap();
+        assert_eq!(headers.get("host"), Some("Example.COM"));
+        assert_eq!(headers.get("X-TOKEN"), Some("AbC=dEf"));
+        assert_eq!(headers.get_all("Cookie"), ["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn unfolds_obsolete_line_folding() {
+        let headers =
+            parse_fields("X-Long: first\r\n  second\r\n\tthird\r\nX-Next: n\r\n\r\n").unwrap();
+        assert_eq!(headers.get("x-long"), Some("first second third"));
+        assert_eq!(headers.get("x-next"), Some("n"));
+        assert!(parse_fields(" folded: first\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let invalid = [
+            "No colon\r\n\r\n",
+            ": empty name\r\n\r\n",
+            "Space before : colon\r\n\r\n",
+            "Bad\x01Name: value\r\n\r\n",
+            "Host: unterminated",
+        ];
+        for raw in invalid {
+            assert!(parse_fields(raw).is_err(), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn limits_the_number_of_headers() {
+        let fields: String = (0..MAX_HEADERS)
+            .map(|i| format!("X-{}: v\r\n", i))
+            .collect();
+        assert_eq!(
+            parse_fields(&format!("{}\r\n", fields)).unwrap().len(),
+            MAX_HEADERS
+        );
+        assert!(parse_fields(&format!("{}X-Extra: v\r\n\r\n", fields)).is_err());
+    }
+
+    #[test]
+    fn limits_line_length() {
+        let longest = "a".repeat(MAX_LINE_LENGTH);
+        assert_eq!(
+            read_line(&mut format!("{}\r\n", longest).as_bytes()).unwrap(),
+            longest
+        );
+        assert_eq!(
+            read_line(&mut format!("{}\n", longest).as_bytes()).unwrap(),
+            longest
+        );
+        assert!(read_line(&mut format!("{}a\r\n", longest).as_bytes()).is_err());
+
+        let value = "v".repeat(MAX_LINE_LENGTH);
+        assert!(parse(format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", value).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn reads_non_utf8_bytes_as_latin1() {
+        assert_eq!(read_line(&mut &b"caf\xe9\r\n"[..]).unwrap(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn parses_cookies_from_every_cookie_header() {
+        let request =
+            parse(b"GET / HTTP/1.1\r\nCookie: a=1; b=x=y\r\nCookie: c=3;bad\r\n\r\n").unwrap();
+        let cookies: Vec<(String, String)> = request
+            .cookies()
+            .into_iter()
+            .map(|cookie| (cookie.name, cookie.value))
+            .collect();
+        assert_eq!(
+            cookies,
+            [("a", "1"), ("b", "x=y"), ("c", "3")].map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+    }
+}
@@ -1,12 +1,11 @@
-use crate::http_server::{ContentType, Cookie};
-use std::collections::HashMap;
+use crate::http_server::{ContentType, Cookie, Headers};
 
 #[derive(Debug, Clone)]
 pub struct Response {
     pub http_version: String,
     pub status_code: u16,
     pub reason_phrase: String,
-    pub headers: Option<HashMap<String, String>>,
+    pub headers: Headers,
     pub body: Option<Vec<u8>>,
     pub location: Option<String>,
 }
@@ -17,7 +16,7 @@ impl Default for Response {
             http_version: "HTTP/1.1".to_string(),
             status_code: 200,
             reason_phrase: "OK".to_string(),
-            headers: None,
+            headers: Headers::new(),
             body: None,
             location: None,
         }
@@ -27,27 +26,21 @@ pub struct ResponseBuilder {
     pub response: Response,
 }
 
+impl Default for ResponseBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ResponseBuilder {
     pub fn content_type(mut self, content_type: ContentType) -> Self {
-        if self.response.headers.is_none() {
-            self.response.headers = Some(HashMap::new());
-        }
-        self.response
-            .headers
-            .as_mut()
-            .unwrap()
-            .insert("Content-Type".to_string(), content_type.to_string());
+        self.response.headers.insert("Content-Type", content_type);
         self
     }
     pub fn temp_redirect(mut self, location: impl ToString) -> Self {
-        if self.response.headers.is_none() {
-            self.response.headers = Some(HashMap::new());
-        }
         self.response
             .headers
-            .as_mut()
-            .unwrap()
-            .insert("Location".to_string(), location.to_string());
+            .insert("Location", location.to_string());
         self = self.status_code(302);
         self
     }
@@ -59,14 +52,9 @@ impl ResponseBuilder {
         self
     }
     fn content_length(mut self, content_length: usize) -> Self {
-        if self.response.headers.is_none() {
-            self.response.headers = Some(HashMap::new());
-        }
         self.response
             .headers
-            .as_mut()
-            .unwrap()
-            .insert("Content-Length".to_string(), content_length.to_string());
+            .insert("Content-Length", content_length);
         self
     }
 
@@ -90,8 +78,13 @@ impl ResponseBuilder {
         self
     }
 
-    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
-        self.response.headers = Some(headers);
+    pub fn header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.response.headers.insert(name, value);
+        self
+    }
+
+    pub fn headers(mut self, headers: Headers) -> Self {
+        self.response.headers = headers;
         self
     }
     pub fn body_bytes(mut self, body: Vec<u8>) -> Self {
@@ -101,18 +94,37 @@ impl ResponseBuilder {
         self
     }
     pub fn cookie(mut self, cookie: Cookie) -> Self {
-        if self.response.headers.is_none() {
-            self.response.headers = Some(HashMap::new());
-        }
-
-        if let Some(map) = self.response.headers.as_mut() {
-            map.insert("Set-Cookie".to_string(), cookie.cookie_string());
-        }
+        self.response
+            .headers
+            .append("Set-Cookie", cookie.cookie_string());
 
         self
     }
 
+    pub fn remove_cookie(self, cookie: Cookie) -> Self {
+        self.cookie(cookie.into_removal())
+    }
+
     pub fn build(self) -> Response {
         self.response
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_every_cookie_in_its_own_header() {
+        let response = ResponseBuilder::new()
+            .cookie(Cookie::new("a", "1".to_string()))
+            .cookie(Cookie::new("b", "2".to_string()).http_only(true))
+            .remove_cookie(Cookie::new("c", String::new()))
+            .build();
+        let set_cookies = response.headers.get_all("set-cookie");
+        assert_eq!(set_cookies.len(), 3);
+        assert_eq!(set_cookies[0], "a=1");
+        assert_eq!(set_cookies[1], "b=2; HttpOnly");
+        assert!(set_cookies[2].starts_with("c=; Expires="));
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use super::{HttpMethod, Middleware, RequireAuth, RequireRole, RequireSecondFactor};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParamKind {
+    Any,
+    Int,
+    Uuid,
+}
+
+impl ParamKind {
+    fn parse(kind: &str) -> std::result::Result<Self, String> {
+        match kind {
+            "int" => Ok(ParamKind::Int),
+            "uuid" => Ok(ParamKind::Uuid),
+            _ => Err(format!("Unknown path parameter type <{}>", kind)),
+        }
+    }
+
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            ParamKind::Any => true,
+            ParamKind::Int => value.parse::<i64>().is_ok(),
+            ParamKind::Uuid => is_uuid(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Static(String),
+    // `:name` or `:name<int>`, matches exactly one segment
+    Param { name: String, kind: ParamKind },
+    // anonymous `*`, matches exactly one segment
+    Any,
+    // trailing `*name`, matches one or more remaining segments
+    CatchAll(String),
+}
+
+impl Segment {
+    fn parse(segment: &str) -> std::result::Result<Self, String> {
+        if let Some(param) = segment.strip_prefix(':') {
+            let (name, kind) = match param.split_once('<') {
+                Some((name, kind)) => {
+                    let kind = kind
+                        .strip_suffix('>')
+                        .ok_or_else(|| format!("Unclosed parameter type in {}", segment))?;
+                    (name, ParamKind::parse(kind)?)
+                }
+                None => (param, ParamKind::Any),
+            };
+            if name.is_empty() {
+                return Err(format!("Path parameter without a name: {}", segment));
+            }
+            return Ok(Segment::Param {
+                name: name.to_string(),
+                kind,
+            });
+        }
+        match segment.strip_prefix('*') {
+            Some("") => Ok(Segment::Any),
+            Some(name) => Ok(Segment::CatchAll(name.to_string())),
+            None => Ok(Segment::Static(segment.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Static(s) => write!(f, "{}", s),
+            Segment::Param { name, kind } => match kind {
+                ParamKind::Any => write!(f, ":{}", name),
+                ParamKind::Int => write!(f, ":{}<int>", name),
+                ParamKind::Uuid => write!(f, ":{}<uuid>", name),
+            },
+            Segment::Any => write!(f, "*"),
+            Segment::CatchAll(name) => write!(f, "*{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MethodFilter {
+    Only(Vec<HttpMethod>),
+    Any,
+}
+
+pub struct Route {
+    pub methods: MethodFilter,
+    pub path_segments: Vec<Segment>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub(crate) error: Option<String>,
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Route {
+    pub fn new() -> Self {
+        Route {
+            methods: MethodFilter::Only(vec![HttpMethod::GET]),
+            path_segments: Vec::new(),
+            middleware: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn http_method(mut self, method: HttpMethod) -> Self {
+        self.methods = MethodFilter::Only(vec![method]);
+        self
+    }
+
+    pub fn http_methods(mut self, methods: &[HttpMethod]) -> Self {
+        self.methods = MethodFilter::Only(methods.to_vec());
+        self
+    }
+
+    pub fn any_method(mut self) -> Self {
+        self.methods = MethodFilter::Any;
+        self
+    }
+
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    // Answers 401 unless the request was authenticated, see `RequireAuth`.
+    pub fn require_auth(self) -> Self {
+        self.middleware(RequireAuth::new())
+    }
+
+    // Answers 403 unless the principal has one of `roles`, see `RequireRole`.
+    pub fn require_role(self, roles: &[impl ToString]) -> Self {
+        self.middleware(RequireRole::any_of(roles))
+    }
+
+    // Answers 403 unless the principal proved a second factor, see
+    // `RequireSecondFactor`.
+    pub fn require_second_factor(self) -> Self {
+        self.middleware(RequireSecondFactor::new())
+    }
+
+    pub fn path(mut self, path: String) -> Self {
+        let parsed: std::result::Result<Vec<Segment>, String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(Segment::parse)
+            .collect();
+        match parsed {
+            Ok(segments) => {
+                let last = segments.len().saturating_sub(1);
+                let misplaced_catch_all = segments
+                    .iter()
+                    .take(last)
+                    .any(|s| matches!(s, Segment::CatchAll(_)));
+                if misplaced_catch_all {
+                    self.error = Some(format!("Catch-all must be the last segment in {}", path));
+                }
+                self.path_segments = segments;
+            }
+            Err(e) => self.error = Some(format!("Invalid route {}: {}", path, e)),
+        }
+        self
+    }
+
+    pub fn pattern(&self) -> String {
+        let segments: Vec<String> = self.path_segments.iter().map(Segment::to_string).collect();
+        format!("/{}", segments.join("/"))
+    }
+}
+
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    let lengths = [8, 4, 4, 4, 12];
+    groups.len() == lengths.len()
+        && groups
+            .iter()
+            .zip(lengths)
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
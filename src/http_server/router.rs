@@ -1,92 +1,594 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::error::Result;
+use crate::utils::Json;
 
-use super::{HttpMethod, Request, Response, ResponseBuilder};
+use super::{
+    ContentType, HttpMethod, MethodFilter, Middleware, Next, ParamKind, Request, RequireAuth,
+    RequireRole, RequireSecondFactor, Response, ResponseBuilder, Route, Segment, WithMiddleware,
+};
 
 pub trait RouteHandler: Send + Sync {
     fn handle(&self, request: Request) -> Result<Response>;
 }
 
 pub struct Router {
-    pub routes: HashMap<Route, Box<dyn RouteHandler>>,
-    pub not_found_response: Response,
+    root: Node,
+    middleware: Vec<Arc<dyn Middleware>>,
+    errors: Vec<String>,
 }
 
-#[derive(PartialEq, Eq, Hash)]
-pub struct Route {
-    pub http_method: HttpMethod,
-    pub path_segments: Vec<String>,
+#[derive(Default)]
+struct Endpoints {
+    methods: HashMap<HttpMethod, Arc<dyn RouteHandler>>,
+    any: Option<Arc<dyn RouteHandler>>,
 }
 
-impl Route {
-    pub fn new() -> Self {
-        Route {
-            http_method: HttpMethod::GET,
-            path_segments: Vec::new(),
+impl Endpoints {
+    fn is_empty(&self) -> bool {
+        self.methods.is_empty() && self.any.is_none()
+    }
+
+    // HEAD falls back to the GET handler when it has no handler of its own.
+    fn handler(&self, method: &HttpMethod) -> Option<&Arc<dyn RouteHandler>> {
+        self.methods
+            .get(method)
+            .or_else(|| match method {
+                HttpMethod::HEAD => self.methods.get(&HttpMethod::GET),
+                _ => None,
+            })
+            .or(self.any.as_ref())
+    }
+
+    fn add(
+        &mut self,
+        methods: &MethodFilter,
+        handler: Arc<dyn RouteHandler>,
+    ) -> std::result::Result<(), String> {
+        match methods {
+            MethodFilter::Any if self.any.is_some() => {
+                Err("a handler for any method is already registered".to_string())
+            }
+            MethodFilter::Any => {
+                self.any = Some(handler);
+                Ok(())
+            }
+            MethodFilter::Only(methods) => {
+                if let Some(method) = methods.iter().find(|m| self.methods.contains_key(m)) {
+                    return Err(format!("{} is already registered", method));
+                }
+                for method in methods {
+                    self.methods.insert(method.clone(), Arc::clone(&handler));
+                }
+                Ok(())
+            }
         }
     }
 
-    pub fn http_method(mut self, method: HttpMethod) -> Self {
-        self.http_method = method;
-        self
+    fn merge(
+        &mut self,
+        other: Endpoints,
+        middleware: &[Arc<dyn Middleware>],
+    ) -> std::result::Result<(), String> {
+        if let Some(method) = other.methods.keys().find(|m| self.methods.contains_key(m)) {
+            return Err(format!("{} is already registered", method));
+        }
+        if other.any.is_some() && self.any.is_some() {
+            return Err("a handler for any method is already registered".to_string());
+        }
+        for (method, handler) in other.methods {
+            self.methods
+                .insert(method, wrap(middleware.to_vec(), handler));
+        }
+        if let Some(handler) = other.any {
+            self.any = Some(wrap(middleware.to_vec(), handler));
+        }
+        Ok(())
     }
 
-    pub fn path(mut self, path: String) -> Self {
-        let segments: Vec<String> = path
-            .split('/')
-            .filter(|s| !s.is_empty())
-            .map(String::from)
-            .collect();
-        self.path_segments = segments;
-        self
+    fn allowed_methods(&self) -> String {
+        let mut methods: Vec<HttpMethod> = self.methods.keys().cloned().collect();
+        if methods.contains(&HttpMethod::GET) {
+            methods.push(HttpMethod::HEAD);
+        }
+        methods.push(HttpMethod::OPTIONS);
+        methods.sort();
+        methods.dedup();
+        methods
+            .iter()
+            .map(HttpMethod::to_string)
+            .collect::<Vec<String>>()
+            .join(", ")
     }
-    pub fn matches(&self, http_method: &HttpMethod, path: &str) -> bool {
-        if &self.http_method != http_method {
-            return false;
+}
+
+// One node per path segment. Lookup walks the request segments and prefers
+// static children over parameters over catch-alls, so the cost depends on the
+// path length rather than the number of registered routes.
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<ParamNode>,
+    catch_all: Option<(String, Endpoints)>,
+    endpoints: Endpoints,
+    // set by `Router::not_found_response`, applies to every path below this node
+    not_found: Option<Response>,
+    // Middleware of the routers nested on this node. Their handlers are already
+    // wrapped in it, this is for the responses the router answers itself.
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+struct ParamNode {
+    // None for an anonymous `*` segment
+    name: Option<String>,
+    kind: ParamKind,
+    node: Box<Node>,
+}
+
+impl ParamNode {
+    fn segment(&self) -> Segment {
+        match &self.name {
+            Some(name) => Segment::Param {
+                name: name.clone(),
+                kind: self.kind.clone(),
+            },
+            None => Segment::Any,
         }
+    }
+}
+
+type Params = Vec<(String, String)>;
 
-        let request_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+impl Node {
+    fn insert(&mut self, segments: &[Segment]) -> std::result::Result<&mut Endpoints, String> {
+        match segments.split_last() {
+            Some((Segment::CatchAll(name), parent)) => {
+                let (existing, endpoints) = self
+                    .descend(parent)?
+                    .catch_all
+                    .get_or_insert_with(|| (name.clone(), Endpoints::default()));
+                if existing != name {
+                    return Err(format!("*{} conflicts with *{}", name, existing));
+                }
+                Ok(endpoints)
+            }
+            _ => Ok(&mut self.descend(segments)?.endpoints),
+        }
+    }
 
-        if self.path_segments.len() != request_segments.len() {
-            return false;
+    fn descend(&mut self, segments: &[Segment]) -> std::result::Result<&mut Node, String> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return Ok(self);
+        };
+        match segment {
+            Segment::Static(s) => self.statics.entry(s.clone()).or_default().descend(rest),
+            Segment::Param { name, kind } => self
+                .param_child(Some(name.clone()), kind.clone())?
+                .descend(rest),
+            Segment::Any => self.param_child(None, ParamKind::Any)?.descend(rest),
+            Segment::CatchAll(name) => Err(format!("*{} must be the last segment", name)),
         }
+    }
 
-        for (route_segment, request_segment) in
-            self.path_segments.iter().zip(request_segments.iter())
-        {
-            if route_segment != "*" && route_segment != request_segment {
-                return false;
+    // Grafts `other` onto this node, wrapping each of its handlers in `middleware`.
+    fn merge(
+        &mut self,
+        other: Node,
+        middleware: &[Arc<dyn Middleware>],
+    ) -> std::result::Result<(), String> {
+        self.endpoints.merge(other.endpoints, middleware)?;
+        if other.not_found.is_some() {
+            self.not_found = other.not_found;
+        }
+        self.middleware.extend(other.middleware);
+        for (segment, child) in other.statics {
+            self.statics
+                .entry(segment)
+                .or_default()
+                .merge(child, middleware)?;
+        }
+        if let Some(param) = other.param {
+            self.param_child(param.name, param.kind)?
+                .merge(*param.node, middleware)?;
+        }
+        if let Some((name, endpoints)) = other.catch_all {
+            let (existing, own) = self
+                .catch_all
+                .get_or_insert_with(|| (name.clone(), Endpoints::default()));
+            if *existing != name {
+                return Err(format!("*{} conflicts with *{}", name, existing));
             }
+            own.merge(endpoints, middleware)?;
         }
+        Ok(())
+    }
 
-        true
+    // Collects the nodes along the request path that routers were nested on,
+    // the most specific last. Like `find`, a static child without any falls
+    // back to the param child.
+    fn scope<'a>(&'a self, segments: &[&str], scope: &mut Vec<&'a Node>) -> bool {
+        let nested = self.not_found.is_some() || !self.middleware.is_empty();
+        if nested {
+            scope.push(self);
+        }
+        let deeper = segments.split_first().is_some_and(|(segment, rest)| {
+            self.statics
+                .get(*segment)
+                .is_some_and(|child| child.scope(rest, scope))
+                || self
+                    .param
+                    .as_ref()
+                    .filter(|param| param.kind.accepts(segment))
+                    .is_some_and(|param| param.node.scope(rest, scope))
+        });
+        nested || deeper
+    }
+
+    fn param_child(
+        &mut self,
+        name: Option<String>,
+        kind: ParamKind,
+    ) -> std::result::Result<&mut Node, String> {
+        let param = self.param.get_or_insert_with(|| ParamNode {
+            name: name.clone(),
+            kind: kind.clone(),
+            node: Box::default(),
+        });
+        if param.name != name || param.kind != kind {
+            let requested = ParamNode {
+                name,
+                kind,
+                node: Box::default(),
+            };
+            return Err(format!(
+                "{} conflicts with {}",
+                requested.segment(),
+                param.segment()
+            ));
+        }
+        Ok(&mut param.node)
+    }
+
+    fn find<'a>(
+        &'a self,
+        segments: &[&str],
+        accept: &dyn Fn(&Endpoints) -> bool,
+        params: &mut Params,
+        invalid: &mut Option<String>,
+        path: &mut Vec<&'a Node>,
+    ) -> Option<&'a Endpoints> {
+        path.push(self);
+        let depth = path.len();
+        let Some((segment, rest)) = segments.split_first() else {
+            if accept(&self.endpoints) {
+                return Some(&self.endpoints);
+            }
+            path.pop();
+            return None;
+        };
+        if let Some(child) = self.statics.get(*segment) {
+            if let Some(found) = child.find(rest, accept, params, invalid, path) {
+                return Some(found);
+            }
+        }
+        if let Some(param) = &self.param {
+            let mark = params.len();
+            if let Some(name) = &param.name {
+                params.push((name.clone(), segment.to_string()));
+            }
+            if let Some(found) = param.node.find(rest, accept, params, invalid, path) {
+                if param.kind.accepts(segment) {
+                    return Some(found);
+                }
+                invalid.get_or_insert_with(|| {
+                    format!("Invalid value for path parameter {}", param.segment())
+                });
+                path.truncate(depth);
+            }
+            params.truncate(mark);
+        }
+        if let Some((name, endpoints)) = &self.catch_all {
+            if accept(endpoints) {
+                params.push((name.clone(), segments.join("/")));
+                return Some(endpoints);
+            }
+        }
+        path.pop();
+        None
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Router {
     pub fn new() -> Self {
         Router {
-            routes: HashMap::new(),
-            not_found_response: default_not_found_response(),
+            root: Node::default(),
+            middleware: Vec::new(),
+            errors: Vec::new(),
         }
     }
-    pub fn route(&self, method: HttpMethod, path: &str) -> Option<&Box<dyn RouteHandler>> {
-        for (route, handler) in &self.routes {
-            if route.matches(&method, path) {
-                return Some(handler);
+    fn dispatch(&self, mut request: Request) -> Result<Response> {
+        let decoded = request.path_segments();
+        let segments: Vec<&str> = decoded.iter().map(String::as_str).collect();
+        let mut params = Vec::new();
+        let mut invalid = None;
+        let method = request.method.clone();
+        let accept = |endpoints: &Endpoints| endpoints.handler(&method).is_some();
+
+        let response = match self.root.find(
+            &segments,
+            &accept,
+            &mut params,
+            &mut invalid,
+            &mut Vec::new(),
+        ) {
+            Some(endpoints) => {
+                let handler = endpoints
+                    .handler(&method)
+                    .ok_or("Matched route without a handler")?;
+                request.params = params.into_iter().collect();
+                handler.handle(request)?
+            }
+            None => {
+                let mut any_params = Vec::new();
+                let mut path = Vec::new();
+                let any_method = |endpoints: &Endpoints| !endpoints.is_empty();
+                let (response, scope) = match self.root.find(
+                    &segments,
+                    &any_method,
+                    &mut any_params,
+                    &mut invalid,
+                    &mut path,
+                ) {
+                    Some(endpoints) if method == HttpMethod::OPTIONS => {
+                        (options_response(endpoints.allowed_methods()), path)
+                    }
+                    Some(endpoints) => (
+                        method_not_allowed_response(endpoints.allowed_methods()),
+                        path,
+                    ),
+                    None => {
+                        let mut scope = Vec::new();
+                        self.root.scope(&segments, &mut scope);
+                        let response = match invalid {
+                            Some(message) => bad_request_response(message),
+                            None => scope
+                                .iter()
+                                .rev()
+                                .find_map(|node| node.not_found.clone())
+                                .unwrap_or_else(default_not_found_response),
+                        };
+                        (response, scope)
+                    }
+                };
+                // Still goes through the middleware of the groups the path is
+                // in, like their routes would
+                let middleware: Vec<Arc<dyn Middleware>> = scope
+                    .iter()
+                    .flat_map(|node| node.middleware.iter().cloned())
+                    .collect();
+                let endpoint = |_: Request| Ok(response.clone());
+                Next::new(&middleware, &endpoint).run(request)?
             }
+        };
+
+        if method == HttpMethod::HEAD {
+            return Ok(strip_body(response));
         }
-        None
+        Ok(response)
     }
     pub fn add_route<H: RouteHandler + 'static>(mut self, route: Route, handler: H) -> Self {
-        self.routes.insert(route, Box::new(handler));
+        self.insert_route(route, Arc::new(handler));
+        self
+    }
+    fn insert_route(&mut self, mut route: Route, handler: Arc<dyn RouteHandler>) {
+        if let Some(error) = &route.error {
+            self.errors.push(error.clone());
+            return;
+        }
+        let handler = wrap(std::mem::take(&mut route.middleware), handler);
+        let result = self
+            .root
+            .insert(&route.path_segments)
+            .and_then(|endpoints| endpoints.add(&route.methods, handler));
+        if let Err(e) = result {
+            self.errors
+                .push(format!("Route {}: {}", route.pattern(), e));
+        }
+    }
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+    // Answers 401 on every route of this router unless the request was
+    // authenticated, see `RequireAuth`.
+    pub fn require_auth(self) -> Self {
+        self.middleware(RequireAuth::new())
+    }
+    // Answers 403 on every route of this router unless the principal has
+    // one of `roles`, see `RequireRole` for a custom forbidden page.
+    pub fn require_role(self, roles: &[impl ToString]) -> Self {
+        self.middleware(RequireRole::any_of(roles))
+    }
+    // Answers 403 on every route of this router unless the principal proved
+    // a second factor, see `RequireSecondFactor`.
+    pub fn require_second_factor(self) -> Self {
+        self.middleware(RequireSecondFactor::new())
+    }
+    pub(crate) fn prepend_middleware(&mut self, middleware: Vec<Arc<dyn Middleware>>) {
+        self.middleware.splice(0..0, middleware);
+    }
+    // Mounts every route of `router` below `prefix`. The nested router's
+    // middleware and not-found response only apply below `prefix`, to its own
+    // routes and to the 404, 405 and OPTIONS responses there. Routers nested
+    // at `/` sit side by side, so their middleware only covers the first
+    // segments they have routes under.
+    pub fn nest(mut self, prefix: &str, mut router: Router) -> Self {
+        let prefix = Route::new().path(prefix.to_string());
+        if let Some(error) = prefix.error {
+            self.errors.push(error);
+            return self;
+        }
+        self.errors.extend(router.errors);
+        let middleware = &router.middleware;
+        if prefix.path_segments.is_empty() {
+            let root = &mut router.root;
+            let children = root
+                .statics
+                .values_mut()
+                .chain(root.param.as_mut().map(|param| param.node.as_mut()));
+            for child in children {
+                child.middleware.splice(0..0, middleware.iter().cloned());
+            }
+        }
+        let result = self.root.descend(&prefix.path_segments).and_then(|node| {
+            if !prefix.path_segments.is_empty() {
+                node.middleware.extend(middleware.iter().cloned());
+            }
+            node.merge(router.root, middleware)
+        });
+        if let Err(e) = result {
+            self.errors
+                .push(format!("Nesting under {}: {}", prefix.pattern(), e));
+        }
         self
     }
+    pub fn group<F>(self, prefix: &str, build: F) -> Self
+    where
+        F: FnOnce(Router) -> Router,
+    {
+        self.nest(prefix, build(Router::new()))
+    }
     pub fn not_found_response(mut self, response: Response) -> Self {
-        self.not_found_response = response;
+        self.root.not_found = Some(response);
         self
     }
+    pub fn validate(&self) -> Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(format!("Invalid router configuration: {}", self.errors.join("; ")).into())
+    }
+}
+
+impl RouteHandler for Router {
+    fn handle(&self, request: Request) -> Result<Response> {
+        let endpoint = |request: Request| self.dispatch(request);
+        Next::new(&self.middleware, &endpoint).run(request)
+    }
+}
+
+fn wrap(
+    middleware: Vec<Arc<dyn Middleware>>,
+    handler: Arc<dyn RouteHandler>,
+) -> Arc<dyn RouteHandler> {
+    if middleware.is_empty() {
+        return handler;
+    }
+    Arc::new(WithMiddleware {
+        middleware,
+        handler,
+    })
+}
+
+// HEAD gets the GET response without its body, but keeps the Content-Length
+// the body would have had.
+fn strip_body(mut response: Response) -> Response {
+    if let Some(body) = response.body.take() {
+        if !response.headers.contains("content-length") {
+            response.headers.insert("Content-Length", body.len());
+        }
+    }
+    response
+}
+
+fn options_response(allow: String) -> Response {
+    ResponseBuilder::new()
+        .status_code(204)
+        .reason_phrase("No Content".to_string())
+        .header("Allow", allow)
+        .build()
+}
+
+pub fn method_not_allowed_response(allow: String) -> Response {
+    ResponseBuilder::new()
+        .status_code(405)
+        .reason_phrase("Method Not Allowed".to_string())
+        .header("Allow", allow)
+        .content_type(ContentType::PlainTest)
+        .body_string("Method Not Allowed".to_string())
+        .build()
+}
+
+pub fn bad_request_response(message: impl ToString) -> Response {
+    ResponseBuilder::new()
+        .status_code(400)
+        .reason_phrase("Bad Request".to_string())
+        .content_type(ContentType::PlainTest)
+        .body_string(message.to_string())
+        .build()
+}
+
+pub fn json_response(status_code: u16, json: Json) -> Response {
+    ResponseBuilder::new()
+        .status_code(status_code)
+        .reason_phrase(reason_phrase(status_code).to_string())
+        .content_type(ContentType::Json)
+        .body_string(json.to_string())
+        .build()
+}
+
+fn reason_phrase(status_code: u16) -> &'static str {
+    match status_code {
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        _ => "OK",
+    }
+}
+
+pub fn internal_server_error_response() -> Response {
+    ResponseBuilder::new()
+        .status_code(500)
+        .reason_phrase("Internal Server Error".to_string())
+        .content_type(ContentType::PlainTest)
+        .body_string("Internal Server Error".to_string())
+        .build()
+}
+
+pub fn too_many_requests_response(retry_after: Duration) -> Response {
+    // Round up so a client honouring the header never comes back early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    ResponseBuilder::new()
+        .status_code(429)
+        .reason_phrase("Too Many Requests".to_string())
+        .header("Retry-After", seconds)
+        .content_type(ContentType::PlainTest)
+        .body_string("Too many attempts, try again later".to_string())
+        .build()
+}
+
+pub fn default_forbidden_response() -> Response {
+    let page_403 = include_str!("../../assets/403.html").to_string();
+
+    ResponseBuilder::new()
+        .status_code(403)
+        .reason_phrase("Forbidden".to_string())
+        .content_type(ContentType::Html)
+        .body_string(page_403)
+        .build()
 }
 
 pub fn default_not_found_response() -> Response {
@@ -98,3 +600,298 @@ pub fn default_not_found_response() -> Response {
         .body_string(page_404)
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_server::{Extensions, Headers};
+
+    // Answers with its name followed by the matched params, sorted.
+    struct Named(&'static str);
+
+    impl RouteHandler for Named {
+        fn handle(&self, request: Request) -> Result<Response> {
+            let mut params: Vec<String> = request
+                .params
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            params.sort();
+            params.insert(0, self.0.to_string());
+            Ok(ResponseBuilder::new().body_string(params.join(" ")).build())
+        }
+    }
+
+    fn request(method: HttpMethod, path: &str) -> Request {
+        Request {
+            method,
+            path: path.to_string(),
+            query: None,
+            http_version: "HTTP/1.1".to_string(),
+            headers: Headers::new(),
+            body: Vec::new(),
+            params: HashMap::new(),
+            extensions: Extensions::new(),
+            peer_addr: None,
+        }
+    }
+
+    fn send(router: &Router, method: HttpMethod, path: &str) -> Response {
+        router.handle(request(method, path)).unwrap()
+    }
+
+    fn body(response: &Response) -> String {
+        String::from_utf8(response.body.clone().unwrap_or_default()).unwrap()
+    }
+
+    #[test]
+    fn static_beats_param_beats_catch_all() {
+        // Registered least specific first, so order can't be what decides
+        let router = Router::new()
+            .add_route(HttpMethod::get("/images/*path"), Named("catch-all"))
+            .add_route(HttpMethod::get("/images/:name"), Named("param"))
+            .add_route(HttpMethod::get("/images/logo"), Named("static"));
+        router.validate().unwrap();
+
+        let get = |path| body(&send(&router, HttpMethod::GET, path));
+        assert_eq!(get("/images/logo"), "static");
+        assert_eq!(get("/images/cat"), "param name=cat");
+        assert_eq!(get("/images/a/b"), "catch-all path=a/b");
+    }
+
+    #[test]
+    fn backtracks_from_a_static_dead_end() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/files/special/info"), Named("info"))
+            .add_route(HttpMethod::get("/files/:id/raw"), Named("raw"))
+            .add_route(HttpMethod::get("/files/*rest"), Named("rest"));
+
+        let get = |path| body(&send(&router, HttpMethod::GET, path));
+        assert_eq!(get("/files/special/info"), "info");
+        assert_eq!(get("/files/special/raw"), "raw id=special");
+        assert_eq!(get("/files/special/other"), "rest rest=special/other");
+        assert_eq!(send(&router, HttpMethod::GET, "/files").status_code, 404);
+    }
+
+    #[test]
+    fn rejects_invalid_typed_params() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/users/:id<int>"), Named("user"))
+            .add_route(HttpMethod::get("/keys/:id<uuid>"), Named("key"));
+
+        assert_eq!(
+            body(&send(&router, HttpMethod::GET, "/users/42")),
+            "user id=42"
+        );
+        assert_eq!(
+            send(&router, HttpMethod::GET, "/users/abc").status_code,
+            400
+        );
+        let uuid = "/keys/67e55044-10b1-426f-9247-bb680e5fe0c8";
+        assert_eq!(
+            body(&send(&router, HttpMethod::GET, uuid)),
+            format!("key id={}", &uuid[6..])
+        );
+        assert_eq!(send(&router, HttpMethod::GET, "/keys/42").status_code, 400);
+    }
+
+    #[test]
+    fn falls_back_to_catch_all_for_invalid_typed_params() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/items/:id<int>"), Named("item"))
+            .add_route(HttpMethod::get("/items/*rest"), Named("rest"));
+
+        let get = |path| body(&send(&router, HttpMethod::GET, path));
+        assert_eq!(get("/items/7"), "item id=7");
+        assert_eq!(get("/items/seven"), "rest rest=seven");
+    }
+
+    #[test]
+    fn reports_conflicting_routes() {
+        let conflicts = [
+            Router::new()
+                .add_route(HttpMethod::get("/a"), Named("first"))
+                .add_route(HttpMethod::get("/a"), Named("second")),
+            Router::new()
+                .add_route(HttpMethod::get("/a/:id"), Named("first"))
+                .add_route(HttpMethod::post("/a/:name"), Named("second")),
+            Router::new()
+                .add_route(HttpMethod::get("/a/:id<int>"), Named("first"))
+                .add_route(HttpMethod::post("/a/:id"), Named("second")),
+            Router::new()
+                .add_route(HttpMethod::get("/a/*rest"), Named("first"))
+                .add_route(HttpMethod::post("/a/*path"), Named("second")),
+            Router::new()
+                .add_route(HttpMethod::any("/a"), Named("first"))
+                .add_route(HttpMethod::any("/a"), Named("second")),
+            Router::new().add_route(HttpMethod::get("/a/*rest/b"), Named("misplaced")),
+            Router::new().add_route(HttpMethod::get("/a/:id<float>"), Named("unknown type")),
+        ];
+        for router in conflicts {
+            assert!(router.validate().is_err());
+        }
+
+        let router = Router::new()
+            .add_route(HttpMethod::get("/a/:id"), Named("get"))
+            .add_route(HttpMethod::post("/a/:id"), Named("post"))
+            .add_route(HttpMethod::get("/a/:id/b"), Named("nested"));
+        router.validate().unwrap();
+    }
+
+    #[test]
+    fn not_found_falls_back_to_the_param_child() {
+        let not_found = ResponseBuilder::new()
+            .status_code(404)
+            .body_string("no such user page".to_string())
+            .build();
+        let router = Router::new()
+            .add_route(HttpMethod::get("/users/new"), Named("new"))
+            .group("/users/:id", |user| {
+                user.add_route(HttpMethod::get("/posts"), Named("posts"))
+                    .not_found_response(not_found)
+            });
+        router.validate().unwrap();
+
+        let response = send(&router, HttpMethod::GET, "/users/new/other");
+        assert_eq!(response.status_code, 404);
+        assert_eq!(body(&response), "no such user page");
+        assert_eq!(
+            body(&send(&router, HttpMethod::GET, "/users/7/other")),
+            "no such user page"
+        );
+        assert_ne!(
+            body(&send(&router, HttpMethod::GET, "/other")),
+            "no such user page"
+        );
+    }
+
+    // Appends its name to an `X-Seen` response header.
+    struct Tag(&'static str);
+
+    impl Middleware for Tag {
+        fn handle(&self, request: Request, next: Next) -> Result<Response> {
+            let mut response = next.run(request)?;
+            response.headers.append("X-Seen", self.0);
+            Ok(response)
+        }
+    }
+
+    fn seen(response: &Response) -> Vec<&str> {
+        response.headers.get_all("x-seen")
+    }
+
+    #[test]
+    fn group_middleware_covers_the_whole_group() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/public"), Named("public"))
+            .group("/admin", |admin| {
+                admin
+                    .middleware(Tag("admin"))
+                    .add_route(HttpMethod::get("/users"), Named("users"))
+                    .group("/keys/:id<int>", |keys| {
+                        keys.middleware(Tag("keys"))
+                            .add_route(HttpMethod::delete("/"), Named("key"))
+                    })
+            });
+        router.validate().unwrap();
+
+        let cases = [
+            (HttpMethod::GET, "/admin/users", 200, vec!["admin"]),
+            (HttpMethod::GET, "/admin/other", 404, vec!["admin"]),
+            (HttpMethod::POST, "/admin/users", 405, vec!["admin"]),
+            (HttpMethod::OPTIONS, "/admin/users", 204, vec!["admin"]),
+            (
+                HttpMethod::DELETE,
+                "/admin/keys/1",
+                200,
+                vec!["keys", "admin"],
+            ),
+            (HttpMethod::GET, "/admin/keys/1", 405, vec!["keys", "admin"]),
+            (
+                HttpMethod::GET,
+                "/admin/keys/1/other",
+                404,
+                vec!["keys", "admin"],
+            ),
+            (HttpMethod::DELETE, "/admin/keys/one", 400, vec!["admin"]),
+            (HttpMethod::GET, "/public", 200, vec![]),
+            (HttpMethod::POST, "/public", 405, vec![]),
+            (HttpMethod::GET, "/other", 404, vec![]),
+        ];
+        for (method, path, status_code, tags) in cases {
+            let response = send(&router, method, path);
+            assert_eq!(response.status_code, status_code, "{}", path);
+            assert_eq!(seen(&response), tags, "{}", path);
+        }
+    }
+
+    #[test]
+    fn routers_nested_at_the_root_only_cover_their_own_paths() {
+        let module = |name: &'static str| {
+            Router::new()
+                .middleware(Tag(name))
+                .add_route(HttpMethod::get(&format!("/{}/page", name)), Named(name))
+        };
+        let router = Router::new()
+            .nest("/", module("first"))
+            .nest("/", module("second"));
+        router.validate().unwrap();
+
+        let cases = [
+            (HttpMethod::GET, "/first/page", 200, vec!["first"]),
+            (HttpMethod::GET, "/first/other", 404, vec!["first"]),
+            (HttpMethod::POST, "/second/page", 405, vec!["second"]),
+            (HttpMethod::GET, "/other", 404, vec![]),
+        ];
+        for (method, path, status_code, tags) in cases {
+            let response = send(&router, method, path);
+            assert_eq!(response.status_code, status_code, "{}", path);
+            assert_eq!(seen(&response), tags, "{}", path);
+        }
+    }
+
+    #[test]
+    fn answers_405_with_allow() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/thing"), Named("get"))
+            .add_route(HttpMethod::post("/thing"), Named("post"));
+
+        let response = send(&router, HttpMethod::DELETE, "/thing");
+        assert_eq!(response.status_code, 405);
+        assert_eq!(
+            response.headers.get("allow"),
+            Some("GET, HEAD, POST, OPTIONS")
+        );
+        assert_eq!(send(&router, HttpMethod::DELETE, "/other").status_code, 404);
+    }
+
+    #[test]
+    fn head_falls_back_to_get() {
+        let router = Router::new().add_route(HttpMethod::get("/thing"), Named("get"));
+
+        let response = send(&router, HttpMethod::HEAD, "/thing");
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.is_none());
+        assert_eq!(response.headers.get("content-length"), Some("3"));
+    }
+
+    #[test]
+    fn answers_options_with_allow() {
+        let router = Router::new()
+            .add_route(HttpMethod::get("/thing"), Named("get"))
+            .add_route(HttpMethod::put("/thing"), Named("put"))
+            .add_route(HttpMethod::options("/custom"), Named("custom"));
+
+        let response = send(&router, HttpMethod::OPTIONS, "/thing");
+        assert_eq!(response.status_code, 204);
+        assert_eq!(
+            response.headers.get("allow"),
+            Some("GET, HEAD, PUT, OPTIONS")
+        );
+        // Its own OPTIONS handler wins
+        assert_eq!(
+            body(&send(&router, HttpMethod::OPTIONS, "/custom")),
+            "custom"
+        );
+    }
+}
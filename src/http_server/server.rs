@@ -1,14 +1,19 @@
-use super::{Request, Response, Router};
+use super::{
+    bad_request_response, internal_server_error_response, shutdown::TrackedConnection, CookieKeys,
+    Headers, Request, RequestId, Response, RouteHandler, Router, ShutdownHandle,
+};
 use crate::{
     threadpool::ThreadPool,
-    utils::logger,
+    utils::{logger, logger_backend},
     error::Result
 };
 use std::{
-    collections::HashMap,
-    io::{BufReader, Write},
+    io::{BufRead, BufReader, ErrorKind, Write},
     net::{TcpListener, TcpStream},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
@@ -16,22 +21,94 @@ pub struct Server {
     pub listener: TcpListener,
     pub threadpool: ThreadPool,
     pub router: Arc<Router>,
+    pub keep_alive: KeepAlive,
+    pub cookie_keys: Arc<CookieKeys>,
+    pub shutdown: ShutdownHandle,
+    // How long requests in progress get to finish once shutdown starts.
+    pub shutdown_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAlive {
+    pub enabled: bool,
+    pub idle_timeout: Duration,
+    pub max_requests: usize,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
 }
 
 impl Server {
-    pub fn run(self) -> Result<()> {
+    // Serves until `ShutdownHandle::shutdown` is called, then drains and
+    // returns.
+    pub fn run(mut self) -> Result<()> {
         logger::info("Starting Server...");
+        // Connections accepted but not yet picked up by a worker
+        let queued = Arc::new(AtomicUsize::new(0));
         for stream_result in self.listener.incoming() {
+            if self.shutdown.is_shutdown_requested() {
+                break;
+            }
             let router = Arc::clone(&self.router);
+            let keep_alive = self.keep_alive;
+            let cookie_keys = Arc::clone(&self.cookie_keys);
+            let shutdown = self.shutdown.clone();
+            let queued = Arc::clone(&queued);
             match stream_result {
-                Ok(stream) => self.threadpool.execute(move || {
-                    if let Err(e) = handle_connection(stream, &router) {
-                        logger::error(&format!("Error Handling connection: {}", e));
+                Ok(stream) => {
+                    queued.fetch_add(1, Ordering::SeqCst);
+                    // No worker is free for it, so rather than waiting out a
+                    // keep-alive timeout, take the longest idle one
+                    let waiting = self.shutdown.open_connections() + queued.load(Ordering::SeqCst);
+                    if waiting > self.threadpool.size() {
+                        self.shutdown.close_oldest_idle();
                     }
-                }),
+                    self.threadpool.execute(move || {
+                        // Tracked first, so the connection is always counted
+                        // as either open or queued
+                        let tracked = shutdown.track(&stream);
+                        queued.fetch_sub(1, Ordering::SeqCst);
+                        let result = tracked.and_then(|connection| {
+                            handle_connection(
+                                stream,
+                                &router,
+                                keep_alive,
+                                cookie_keys,
+                                &connection,
+                                &queued,
+                            )
+                        });
+                        if let Err(e) = result {
+                            logger::error(&format!("Error Handling connection: {}", e));
+                        }
+                    })
+                }
                 Err(e) => logger::error(&format!("Failed to handle connection {}", e)),
             }
         }
+        drop(self.listener);
+
+        logger::info(&format!(
+            "Stopped accepting connections, waiting up to {}s for {} open ones",
+            self.shutdown_timeout.as_secs(),
+            self.shutdown.open_connections()
+        ));
+        if !self.threadpool.shutdown(self.shutdown_timeout) {
+            let closed = self.shutdown.close_all();
+            logger::error(&format!(
+                "Shutdown timeout passed, closed {} connections",
+                closed
+            ));
+        }
+        logger::info("Server stopped");
+        logger_backend::global_logger().flush();
         Ok(())
     }
 }
@@ -39,28 +116,68 @@ enum ResponseStatus {
     Done,
     Continue,
 }
-fn handle_connection(mut stream: TcpStream, router: &Router) -> Result<()> {
+fn handle_connection(
+    mut stream: TcpStream,
+    router: &Router,
+    keep_alive: KeepAlive,
+    cookie_keys: Arc<CookieKeys>,
+    connection: &TrackedConnection,
+    queued: &AtomicUsize,
+) -> Result<()> {
     let mut buf_reader = BufReader::new(stream.try_clone()?);
+    stream.set_read_timeout(Some(keep_alive.idle_timeout))?;
+    let mut requests_served = 0;
 
     loop {
-        logger::info("Handling a request");
-        stream
-            .set_read_timeout(Some(Duration::from_secs(60)))?;
+        // Shutdown closes idle connections, which ends the wait
+        if !connection.idle() {
+            break;
+        }
+        // Another connection is waiting for a worker, hand it this one or an
+        // older idle one
+        if queued.load(Ordering::SeqCst) > 0 {
+            connection.close_oldest_idle();
+        }
+        if !wait_for_request(&mut buf_reader)? {
+            break;
+        }
+        connection.busy();
         match Request::from_reader(&mut buf_reader) {
-            Ok(request) => {
-                let handler = router.route(request.method.clone(), &request.path);
-
-                let response = match handler {
-                    Some(h) => h.handle(request.clone())?,
-                    None => router.not_found_response.clone(),
+            Ok(mut request) => {
+                requests_served += 1;
+                let request_id = RequestId::next();
+                request.extensions.insert(request_id.clone());
+                request.extensions.insert(Arc::clone(&cookie_keys));
+                let wants_keep_alive = request.keep_alive();
+                let mut response = match router.handle(request) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        logger::error(&format!("Error handling request {}: {}", request_id.0, e));
+                        let mut response = internal_server_error_response();
+                        response.headers.insert("X-Request-Id", request_id.0);
+                        send_response(&mut stream, &response, None)?;
+                        break;
+                    }
                 };
-                match send_response(&mut stream, &response)? {
+                // Checked after handling, so a shutdown during the request
+                // still sends `Connection: close` with its response
+                let persistent = keep_alive.enabled
+                    && !connection.shutting_down()
+                    && wants_keep_alive
+                    && requests_served < keep_alive.max_requests;
+                response.headers.insert("X-Request-Id", request_id.0);
+                let connection = persistent.then(|| {
+                    (keep_alive.idle_timeout, keep_alive.max_requests - requests_served)
+                });
+                match send_response(&mut stream, &response, connection)? {
                     ResponseStatus::Done => break,
                     ResponseStatus::Continue => continue,
                 }
             }
             Err(e) => {
                 logger::error(&format!("Couldn't read request {}", e));
+                // Best effort, the client may already be gone
+                let _ = send_response(&mut stream, &bad_request_response("Bad Request"), None);
                 break;
             }
         }
@@ -69,21 +186,49 @@ fn handle_connection(mut stream: TcpStream, router: &Router) -> Result<()> {
     Ok(())
 }
 
+// Blocks until the next request starts arriving. Returns false when the client
+// closed the connection or the idle timeout elapsed.
+fn wait_for_request(reader: &mut BufReader<TcpStream>) -> Result<bool> {
+    match reader.fill_buf() {
+        Ok(buffer) => Ok(!buffer.is_empty()),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn send_response(
     stream: &mut TcpStream,
     response: &Response,
+    keep_alive: Option<(Duration, usize)>,
 ) -> Result<ResponseStatus> {
     let http_version = &response.http_version;
     let status_code = response.status_code;
     let reason_phrase = &response.reason_phrase;
     let headers = &response.headers;
 
+    let mut header_lines = format_headers(headers);
+    let has_content_length = headers.contains("content-length");
+    let has_no_content = status_code == 204 || (100..200).contains(&status_code);
+    if !has_content_length && !has_no_content {
+        // Without a length the client can't tell where this response ends on a
+        // persistent connection.
+        let body_len = response.body.as_ref().map_or(0, Vec::len);
+        header_lines.push(format!("Content-Length: {}", body_len));
+    }
+    match keep_alive {
+        Some((timeout, max)) => {
+            header_lines.push("Connection: keep-alive".to_string());
+            header_lines.push(format!("Keep-Alive: timeout={}, max={}", timeout.as_secs(), max));
+        }
+        None => header_lines.push("Connection: close".to_string()),
+    }
+
     let header = format!(
         "{} {} {}\r\n{}\r\n\r\n",
         http_version,
         status_code,
         reason_phrase,
-        headers.as_ref().map(format_headers).unwrap_or_default(),
+        header_lines.join("\r\n"),
     );
 
     stream.write_all(header.as_bytes())?;
@@ -91,15 +236,33 @@ fn send_response(
     if let Some(body) = &response.body {
         stream.write_all(body)?;
     }
-    //let connection_header = request.headers.get("Connection").map(|s| s.to_lowercase());
+    stream.flush()?;
 
-    Ok(ResponseStatus::Done)
+    match keep_alive {
+        Some(_) => Ok(ResponseStatus::Continue),
+        None => Ok(ResponseStatus::Done),
+    }
 }
 
-fn format_headers(headers: &HashMap<String, String>) -> String {
+fn format_headers(headers: &Headers) -> Vec<String> {
     headers
         .iter()
         .map(|(k, v)| format!("{}: {}", k, v))
-        .collect::<Vec<String>>()
-        .join("\r\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_repeated_headers_on_separate_lines() {
+        let mut headers = Headers::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Set-Cookie", "b=2; HttpOnly");
+        assert_eq!(
+            format_headers(&headers),
+            ["Set-Cookie: a=1", "Set-Cookie: b=2; HttpOnly"]
+        );
+    }
 }
@@ -1,13 +1,19 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{net::TcpListener, sync::Arc, time::Duration};
 
 use crate::error::Result;
-use crate::http_server::{Router, Server};
+use crate::http_server::{CookieKeys, KeepAlive, Key, Middleware, Router, Server, ShutdownHandle};
 use crate::threadpool::ThreadPool;
+use crate::utils::logger;
 
 pub struct ServerBuilder {
     address: Option<String>,
     thread_count: Option<usize>,
     router: Option<Router>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    keep_alive: KeepAlive,
+    secret_key: Option<Vec<u8>>,
+    previous_secret_keys: Vec<Vec<u8>>,
+    shutdown_timeout: Duration,
 }
 impl Default for ServerBuilder {
     fn default() -> Self {
@@ -15,6 +21,11 @@ impl Default for ServerBuilder {
             address: Some("127.0.0.1:8000".to_string()),
             thread_count: Some(2),
             router: None,
+            middleware: Vec::new(),
+            keep_alive: KeepAlive::default(),
+            secret_key: None,
+            previous_secret_keys: Vec::new(),
+            shutdown_timeout: Duration::from_secs(30),
         }
     }
 }
@@ -35,20 +46,81 @@ impl ServerBuilder {
         self.router = Some(router);
         self
     }
-    pub fn build(self) -> Result<Server> {
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+    pub fn keep_alive(mut self, enabled: bool) -> Self {
+        self.keep_alive.enabled = enabled;
+        self
+    }
+    pub fn keep_alive_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.keep_alive.idle_timeout = idle_timeout;
+        self
+    }
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.keep_alive.max_requests = max_requests;
+        self
+    }
+    // Secret that signed and private cookies are derived from, at least 32 bytes.
+    pub fn secret_key(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret_key = Some(secret.as_ref().to_vec());
+        self
+    }
+    // A retired secret whose cookies are still accepted.
+    pub fn previous_secret_key(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.previous_secret_keys.push(secret.as_ref().to_vec());
+        self
+    }
+    // How long requests in progress get to finish on shutdown before their
+    // connections are closed.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+    // The handle stops the server from another thread, see `ShutdownHandle`.
+    pub fn build(self) -> Result<(Server, ShutdownHandle)> {
         let address = self.address.ok_or("Address is missing")?;
         let thread_count = self
             .thread_count
             .ok_or("Thread count is missing")?;
-        let router = self.router.ok_or("Router is missing")?;
+        if thread_count == 0 {
+            return Err("Thread count must be at least 1".into());
+        }
+        let mut router = self.router.ok_or("Router is missing")?;
+        router.prepend_middleware(self.middleware);
+        router.validate()?;
+        if self.keep_alive.idle_timeout.is_zero() {
+            return Err("Keep-alive timeout must be greater than zero".into());
+        }
+        if self.keep_alive.max_requests == 0 {
+            return Err("Max requests per connection must be at least 1".into());
+        }
+
+        let mut cookie_keys = match &self.secret_key {
+            Some(secret) => CookieKeys::new(Key::from_secret(secret)?),
+            None => {
+                logger::info("No secret key configured, cookies will not survive a restart");
+                CookieKeys::new(Key::generate()?)
+            }
+        };
+        for secret in &self.previous_secret_keys {
+            cookie_keys = cookie_keys.previous(Key::from_secret(secret)?);
+        }
 
         let listener = TcpListener::bind(address)?;
+        let shutdown = ShutdownHandle::new(listener.local_addr()?);
         let threadpool = ThreadPool::new(thread_count);
         let router = Arc::new(router);
-        Ok(Server {
+        let server = Server {
             listener,
             threadpool,
             router,
-        })
+            keep_alive: self.keep_alive,
+            cookie_keys: Arc::new(cookie_keys),
+            shutdown: shutdown.clone(),
+            shutdown_timeout: self.shutdown_timeout,
+        };
+        Ok((server, shutdown))
     }
 }
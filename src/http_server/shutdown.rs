@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::error::Result;
+
+// Stops a running `Server`: it stops accepting, closes idle keep-alive
+// connections, lets requests in progress finish up to the shutdown timeout
+// and then returns from `run`. Clone it to hand to other threads.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+struct ShutdownState {
+    requested: AtomicBool,
+    // Where to connect to wake the accept loop.
+    wake_addr: SocketAddr,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Connection>>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    // When it started waiting for the next request on a keep-alive
+    // connection, if it is.
+    idle_since: Option<Instant>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(local_addr: SocketAddr) -> Self {
+        let mut wake_addr = local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        ShutdownHandle {
+            state: Arc::new(ShutdownState {
+                requested: AtomicBool::new(false),
+                wake_addr,
+                next_id: AtomicU64::new(0),
+                connections: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub fn shutdown(&self) {
+        if self.state.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // Idle connections would otherwise sit out their keep-alive timeout
+        if let Ok(connections) = self.state.connections.lock() {
+            for connection in connections.values().filter(|c| c.idle_since.is_some()) {
+                let _ = connection.stream.shutdown(Shutdown::Read);
+            }
+        }
+        // `accept` can't be interrupted, so hand it a connection to return
+        let _ = TcpStream::connect_timeout(&self.state.wake_addr, Duration::from_secs(1));
+    }
+
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+    }
+
+    // Shuts down on the first SIGINT or SIGTERM and exits the process on the
+    // second, for when draining takes too long. Linux only.
+    pub fn shutdown_on_signals(&self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            signals::listen(self.clone())
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err("Signal handling is only supported on Linux".into())
+        }
+    }
+
+    // Registers a connection so shutdown can find it. It starts out busy and
+    // is forgotten when the returned guard is dropped.
+    pub(crate) fn track(&self, stream: &TcpStream) -> Result<TrackedConnection> {
+        let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+        let connection = Connection {
+            stream: stream.try_clone()?,
+            idle_since: None,
+        };
+        self.state
+            .connections
+            .lock()
+            .map_err(|_| "Failed tracking connection")?
+            .insert(id, connection);
+        Ok(TrackedConnection {
+            handle: self.clone(),
+            id,
+        })
+    }
+
+    // Forcibly closes every connection still open, once the deadline passed.
+    pub(crate) fn close_all(&self) -> usize {
+        match self.state.connections.lock() {
+            Ok(connections) => {
+                for connection in connections.values() {
+                    let _ = connection.stream.shutdown(Shutdown::Both);
+                }
+                connections.len()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    // Closes the connection that has waited longest for its next request, to
+    // free its worker. Returns whether there was one.
+    pub(crate) fn close_oldest_idle(&self) -> bool {
+        if let Ok(mut connections) = self.state.connections.lock() {
+            let oldest = connections
+                .values_mut()
+                .filter(|c| c.idle_since.is_some())
+                .min_by_key(|c| c.idle_since);
+            if let Some(connection) = oldest {
+                // Not picked again while it winds down
+                connection.idle_since = None;
+                let _ = connection.stream.shutdown(Shutdown::Read);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(crate) fn open_connections(&self) -> usize {
+        self.state
+            .connections
+            .lock()
+            .map_or(0, |connections| connections.len())
+    }
+}
+
+pub(crate) struct TrackedConnection {
+    handle: ShutdownHandle,
+    id: u64,
+}
+
+impl TrackedConnection {
+    // Marks the connection as waiting for its next request. Returns false
+    // when the server is shutting down and the connection should close.
+    pub(crate) fn idle(&self) -> bool {
+        self.set_idle(true);
+        // Checked after marking, so a concurrent `shutdown` either sees this
+        // connection as idle or is seen here
+        !self.handle.is_shutdown_requested()
+    }
+
+    pub(crate) fn shutting_down(&self) -> bool {
+        self.handle.is_shutdown_requested()
+    }
+
+    pub(crate) fn busy(&self) {
+        self.set_idle(false);
+    }
+
+    pub(crate) fn close_oldest_idle(&self) -> bool {
+        self.handle.close_oldest_idle()
+    }
+
+    fn set_idle(&self, idle: bool) {
+        if let Ok(mut connections) = self.handle.state.connections.lock() {
+            if let Some(connection) = connections.get_mut(&self.id) {
+                connection.idle_since = idle.then(Instant::now);
+            }
+        }
+    }
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.handle.state.connections.lock() {
+            connections.remove(&self.id);
+        }
+    }
+}
+
+// SIGINT and SIGTERM through a self-pipe: the handler only writes the signal
+// number to a pipe, which is async-signal-safe, and a thread reads it and
+// does the actual work.
+#[cfg(target_os = "linux")]
+mod signals {
+    use std::{
+        fs::File,
+        io::Read,
+        os::fd::{AsRawFd, FromRawFd, IntoRawFd},
+        sync::atomic::{AtomicI32, Ordering},
+        thread,
+    };
+
+    use super::ShutdownHandle;
+    use crate::error::Result;
+    use crate::utils::logger;
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" {
+        // From the C library std already links against, see pipe(2),
+        // write(2) and signal(2). glibc's `signal` keeps the handler
+        // installed and restarts interrupted system calls.
+        fn pipe(fds: *mut i32) -> i32;
+        fn write(fd: i32, buffer: *const u8, len: usize) -> isize;
+        fn signal(signum: i32, handler: usize) -> usize;
+        fn __errno_location() -> *mut i32;
+    }
+
+    extern "C" fn on_signal(signum: i32) {
+        let byte = signum as u8;
+        // SAFETY: write(2) is async-signal-safe and `byte` outlives the call.
+        // errno is restored so the interrupted code doesn't see ours.
+        unsafe {
+            let errno = *__errno_location();
+            write(WRITE_FD.load(Ordering::Relaxed), &byte, 1);
+            *__errno_location() = errno;
+        }
+    }
+
+    pub fn listen(handle: ShutdownHandle) -> Result<()> {
+        let mut fds = [0i32; 2];
+        // SAFETY: `fds` has room for the two descriptors pipe(2) writes.
+        if unsafe { pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(format!("pipe failed: {}", std::io::Error::last_os_error()).into());
+        }
+        // SAFETY: pipe(2) just opened these descriptors and nothing else owns them.
+        let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        if WRITE_FD
+            .compare_exchange(-1, writer.as_raw_fd(), Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err("Signal handling is already installed".into());
+        }
+        // The handler writes to it for the rest of the process' life
+        let _ = writer.into_raw_fd();
+        for signum in [SIGINT, SIGTERM] {
+            // SAFETY: `on_signal` only does async-signal-safe work.
+            let handler = on_signal as extern "C" fn(i32) as usize;
+            if unsafe { signal(signum, handler) } == SIG_ERR {
+                return Err(format!("signal failed: {}", std::io::Error::last_os_error()).into());
+            }
+        }
+
+        thread::spawn(move || {
+            let mut signum = [0u8];
+            let mut received = 0;
+            while reader.read_exact(&mut signum).is_ok() {
+                received += 1;
+                let name = if signum[0] as i32 == SIGINT { "SIGINT" } else { "SIGTERM" };
+                if received == 1 {
+                    logger::info(&format!("Received {}, shutting down", name));
+                    handle.shutdown();
+                } else {
+                    logger::error(&format!("Received {} again, exiting immediately", name));
+                    std::process::exit(1);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
@@ -0,0 +1,142 @@
+// Percent-encoding (RFC 3986) and application/x-www-form-urlencoded helpers
+// shared by query strings and form bodies.
+
+pub fn percent_decode(input: &str) -> String {
+    decode(input, false)
+}
+
+// Like `percent_decode`, but `+` stands for a space as in HTML forms.
+pub fn form_decode(input: &str) -> String {
+    decode(input, true)
+}
+
+fn decode(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 3;
+                        continue;
+                    }
+                    // Malformed escapes are kept as they are
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+// Escapes everything except the RFC 3986 unreserved characters.
+pub fn percent_encode(input: &str) -> String {
+    encode(input, false)
+}
+
+pub fn form_encode(input: &str) -> String {
+    encode(input, true)
+}
+
+fn encode(input: &str, space_as_plus: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b' ' if space_as_plus => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Splits `a=1&b=2&a=3` into decoded pairs, keeping repeated keys in order.
+pub fn parse_form(input: &str) -> Vec<(String, String)> {
+    input
+        .split('&')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (name, value) = part.split_once('=').unwrap_or((part, ""));
+            (form_decode(name), form_decode(value))
+        })
+        .collect()
+}
+
+pub fn serialize_form(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", form_encode(name), form_encode(value)))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%2Fb%20c"), "a/b c");
+        assert_eq!(percent_decode("%c3%A9t%C3%A9"), "été");
+        // Only forms use `+` for a space
+        assert_eq!(percent_decode("a+b"), "a+b");
+        assert_eq!(form_decode("a+b%2B"), "a b+");
+    }
+
+    #[test]
+    fn keeps_malformed_escapes() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%4g"), "%zz%4g");
+        assert_eq!(percent_decode("%%41"), "%A");
+        // Invalid UTF-8 doesn't fail the whole value
+        assert_eq!(percent_decode("a%FFb"), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn encodes_all_but_unreserved() {
+        assert_eq!(percent_encode("a-._~ /&é"), "a-._~%20%2F%26%C3%A9");
+        assert_eq!(form_encode("a b+c"), "a+b%2Bc");
+    }
+
+    #[test]
+    fn parses_forms_with_repeated_keys() {
+        assert_eq!(
+            parse_form("a=1&b=x+y&a=3&&flag&p=%26%25%3D"),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "x y".to_string()),
+                ("a".to_string(), "3".to_string()),
+                ("flag".to_string(), String::new()),
+                ("p".to_string(), "&%=".to_string()),
+            ]
+        );
+        assert!(parse_form("").is_empty());
+    }
+
+    #[test]
+    fn serialized_forms_parse_back() {
+        let pairs = vec![
+            ("user name".to_string(), "a&b=c".to_string()),
+            ("password".to_string(), "100% +sure".to_string()),
+        ];
+        assert_eq!(parse_form(&serialize_form(&pairs)), pairs);
+    }
+}
@@ -3,42 +3,81 @@ use std::path::Path;
 use std::sync::Arc;
 use web_server_core::database::Database;
 use web_server_core::error::Result;
-use web_server_core::handlers::{CssHandler, GetLoginHandler, HomeHandler, PostLoginHandler};
-use web_server_core::http_server::{ContentType, HttpMethod, Request, Response, ResponseBuilder, RouteHandler, Router, ServerBuilder};
-use web_server_core::utils::{logger, logger_backend};
+use web_server_core::crypto::Jwt;
+use web_server_core::handlers::{admin, api_keys, home, login, logout, password, register, token, two_factor, CssHandler};
+use web_server_core::http_server::{bad_request_response, default_not_found_response, init_global_audit_log, Authenticate, BasicAuthenticator, BearerAuthenticator, ContentType, Csrf, HttpMethod, JwtAuthenticator, Request, RequestLogger, Response, ResponseBuilder, RouteHandler, Router, ServerBuilder, SessionAuthenticator, Timing};
+use web_server_core::utils::{logger, logger_backend, random};
 
 fn main() {
     logger_backend::init_global_logger("logs/server.log");
+    if let Err(e) = init_global_audit_log("logs/audit.log") {
+        eprintln!("Failed to open audit log: {:?}", e);
+        std::process::exit(1);
+    }
     if let Err(e) = run_server() {
         eprintln!("Failed to run server: {:?}", e);
         std::process::exit(1);
     }
 }
 fn run_server() -> Result<()> {
-    let database = Arc::new(Database::database_init()?);
+    let admin_password = match std::env::var("ADMIN_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => {
+            let password = random::token(12)?;
+            println!("Generated admin password: {}", password);
+            password
+        }
+    };
+    let database = Arc::new(Database::database_init(&admin_password)?);
+    database.spawn_session_reaper();
+    let jwt_secret = match std::env::var("JWT_SECRET") {
+        Ok(secret) => secret.into_bytes(),
+        Err(_) => random::bytes(32)?,
+    };
+    let jwt = Arc::new(Jwt::new("current", jwt_secret)?.issuer("web_server"));
     let router = Router::new()
-        .add_route(
-            HttpMethod::get("/home"),
-            HomeHandler {
-                database: Arc::clone(&database),
-            },
-        )
+        .nest("/", home::router())
+        .nest("/", login::router(Arc::clone(&database)))
+        .nest("/", logout::router(Arc::clone(&database)))
+        .nest("/", register::router(Arc::clone(&database)))
+        .nest("/", password::router(Arc::clone(&database)))
+        .nest("/", admin::router(Arc::clone(&database)))
+        .nest("/", api_keys::router(Arc::clone(&database)))
+        .nest("/", token::router(Arc::clone(&jwt)))
+        .nest("/", two_factor::router(Arc::clone(&database), "web_server"))
         .add_route(HttpMethod::get("/styles.css"), CssHandler)
         .add_route(HttpMethod::get("/favicon.ico"), FaviconHandler)
-        .add_route(
-            HttpMethod::post("/login"),
-            PostLoginHandler {
-                database: Arc::clone(&database),
-            },
-        )
-        .add_route(HttpMethod::get("/images/*"), GetImageHandler)
-        .add_route(HttpMethod::get("/login"), GetLoginHandler);
+        .group("/images", |images| {
+            images.add_route(HttpMethod::get("/*file"), GetImageHandler)
+        });
 
-    let server = ServerBuilder::new()
+    let mut server_builder = ServerBuilder::new();
+    if let Ok(secret) = std::env::var("SECRET_KEY") {
+        server_builder = server_builder.secret_key(secret);
+    }
+    if let Ok(secret) = std::env::var("PREVIOUS_SECRET_KEY") {
+        server_builder = server_builder.previous_secret_key(secret);
+    }
+    let (server, shutdown) = server_builder
         .address("127.0.0.1:8000")
         .thread_count(4)
         .router(router)
+        .middleware(RequestLogger)
+        .middleware(Timing)
+        .middleware(
+            Authenticate::new()
+                .authenticator(SessionAuthenticator::new(Arc::clone(&database)))
+                .authenticator(BasicAuthenticator::new(Arc::clone(&database)))
+                .authenticator(BearerAuthenticator::new(Arc::clone(&database)))
+                .authenticator(JwtAuthenticator::new(jwt)),
+        )
+        .middleware(
+            Csrf::new()
+                .trusted_origin("http://127.0.0.1:8000")
+                .trusted_origin("http://localhost:8000"),
+        )
         .build()?;
+    shutdown.shutdown_on_signals()?;
     server.run()
 }
 
@@ -56,13 +95,14 @@ impl RouteHandler for FaviconHandler {
 pub struct GetImageHandler;
 impl RouteHandler for GetImageHandler {
     fn handle(&self, request: Request) -> Result<Response> {
-        let path = request.path;
-        logger::info(&format!("Request for image. PATH = {}", path));
-        if !path.starts_with("/images/") {
-            return Err("Invalid path".into());
+        let Some(relative_path) = request.param("file") else {
+            return Ok(bad_request_response("Missing image path"));
+        };
+        logger::info(&format!("Request for image. PATH = {}", request.path));
+        if relative_path.split('/').any(|segment| segment == "..") {
+            return Ok(bad_request_response("Invalid path"));
         }
 
-        let relative_path = &path["/images/".len()..];
         let full_path = Path::new("assets/images/").join(relative_path);
 
         logger::info(&format!(
@@ -75,16 +115,19 @@ impl RouteHandler for GetImageHandler {
             .to_string()
             .starts_with("assets/images/")
         {
-            return Err("Invalid path".into());
+            return Ok(bad_request_response("Invalid path"));
         }
 
         let ext = full_path.extension().and_then(OsStr::to_str);
         let content_type = match ext {
             Some("jpg") | Some("jpeg") => ContentType::Jpeg,
             Some("png") => ContentType::Png,
-            _ => return Err("Unsupported image format".into()),
+            _ => return Ok(bad_request_response("Unsupported image format")),
         };
 
+        if !full_path.is_file() {
+            return Ok(default_not_found_response());
+        }
         let image_data = std::fs::read(&full_path)?;
 
         Ok(ResponseBuilder::new()
@@ -0,0 +1,25 @@
+use std::time::SystemTime;
+
+// A long-lived credential for scripts and CI jobs, sent as
+// `Authorization: Bearer <token>`. Only a SHA-256 hash of the token is kept,
+// the token itself is shown once when the key is created.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    // Public handle for listing and revoking, not a secret.
+    pub id: String,
+    pub username: String,
+    pub name: String,
+    // Roles the key may act with, on top of its owner actually having them.
+    pub scopes: Vec<String>,
+    pub created_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+    // Whether it was created from a session that proved a second factor,
+    // which the key then vouches for.
+    pub second_factor: bool,
+}
+
+impl ApiKey {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
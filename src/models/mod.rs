@@ -1,5 +1,9 @@
+pub mod api_key;
+pub mod password_policy;
 pub mod session;
 pub mod user;
 
+pub use api_key::*;
+pub use password_policy::*;
 pub use session::*;
 pub use user::*;
@@ -0,0 +1,85 @@
+// What passwords users may choose. Following NIST SP 800-63B, length counts
+// and composition rules don't: no "one digit, one symbol", but very common
+// passwords and ones built from the username are refused.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    // Keeps hashing cheap enough that huge passwords can't tie up the server.
+    pub max_length: usize,
+}
+
+// Frequent picks in breach corpora that are long enough to pass `min_length`.
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456789012",
+    "1234567890123",
+    "12345678901234",
+    "qwertyuiopasdfgh",
+    "qwertyuiop123",
+    "qwerty123456",
+    "password1234",
+    "password12345",
+    "passwordpassword",
+    "iloveyou1234",
+    "letmein12345",
+    "welcome12345",
+    "administrator",
+    "abcdefghijkl",
+    "abc123456789",
+    "changeme1234",
+];
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 12,
+            max_length: 128,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn new() -> Self {
+        PasswordPolicy::default()
+    }
+
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    // Why `password` isn't acceptable for `username`, as a message to show them.
+    pub fn validate(&self, username: &str, password: &str) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(format!(
+                "Password must be at least {} characters long",
+                self.min_length
+            ));
+        }
+        if length > self.max_length {
+            return Err(format!(
+                "Password must be at most {} characters long",
+                self.max_length
+            ));
+        }
+        let lowercase = password.to_lowercase();
+        if COMMON_PASSWORDS.contains(&lowercase.as_str()) {
+            return Err("Password is too common".to_string());
+        }
+        if !username.is_empty() && lowercase.contains(&username.to_lowercase()) {
+            return Err("Password must not contain the username".to_string());
+        }
+        let mut chars = password.chars();
+        if let Some(first) = chars.next() {
+            if chars.all(|c| c == first) {
+                return Err("Password must not be a single repeated character".to_string());
+            }
+        }
+        Ok(())
+    }
+}
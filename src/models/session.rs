@@ -1,30 +1,93 @@
-use std::{
-    sync::atomic::AtomicUsize,
-    time::{Instant, SystemTime, UNIX_EPOCH},
-};
+use std::time::{Duration, Instant, SystemTime};
 
-static COUNTER: AtomicUsize = AtomicUsize::new(0);
+use crate::error::Result;
+use crate::utils::random;
+
+// 256 bits, well past the 128 needed to make guessing infeasible.
+const SESSION_ID_BYTES: usize = 32;
+
+// How long sessions live. A session ends after `idle_timeout` without a
+// request, and after `absolute_timeout` no matter how active it is.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionConfig {
+    pub idle_timeout: Duration,
+    pub absolute_timeout: Duration,
+    // How long a user has to enter their second factor after the password.
+    pub pending_timeout: Duration,
+    // How often the background reaper removes expired sessions.
+    pub reap_interval: Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            idle_timeout: Duration::from_secs(30 * 60),
+            absolute_timeout: Duration::from_secs(24 * 60 * 60),
+            pending_timeout: Duration::from_secs(5 * 60),
+            reap_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    // The password was right but the user still has to enter their TOTP
+    // code. Such a session doesn't authenticate requests.
+    PendingSecondFactor,
+    Authenticated,
+    // Authenticated with both the password and a second factor.
+    SecondFactorVerified,
+}
 
 #[derive(Debug, Clone)]
 pub struct Session {
     pub username: String,
     pub session_id: String,
+    pub created_at: Instant,
     pub last_active: Instant,
+    // Synchronizer token forms of this session must send back, see `Csrf`.
+    pub csrf_token: String,
+    pub state: SessionState,
 }
 
 impl Session {
-    pub fn generate_session_id() -> String {
-        let start = SystemTime::now();
-        let since_the_epoch = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Failed to generate session id");
-
-        let counter = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        format!(
-            "{}{}{}",
-            since_the_epoch.as_secs(),
-            since_the_epoch.subsec_nanos(),
-            counter
-        )
+    pub fn new(username: impl ToString) -> Result<Session> {
+        let now = Instant::now();
+        Ok(Session {
+            username: username.to_string(),
+            session_id: Session::generate_session_id()?,
+            created_at: now,
+            last_active: now,
+            csrf_token: random::token(SESSION_ID_BYTES)?,
+            state: SessionState::Authenticated,
+        })
+    }
+
+    pub fn state(mut self, state: SessionState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn generate_session_id() -> Result<String> {
+        random::token(SESSION_ID_BYTES)
+    }
+
+    // Wall-clock times for display; `Instant`s can't be shown directly.
+    pub fn created_at_time(&self) -> SystemTime {
+        SystemTime::now() - self.created_at.elapsed()
+    }
+
+    pub fn last_active_time(&self) -> SystemTime {
+        SystemTime::now() - self.last_active.elapsed()
+    }
+
+    pub fn is_expired(&self, config: &SessionConfig, now: Instant) -> bool {
+        if self.state == SessionState::PendingSecondFactor
+            && now.saturating_duration_since(self.created_at) >= config.pending_timeout
+        {
+            return true;
+        }
+        now.saturating_duration_since(self.last_active) >= config.idle_timeout
+            || now.saturating_duration_since(self.created_at) >= config.absolute_timeout
     }
 }
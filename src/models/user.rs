@@ -1,5 +1,154 @@
+use std::time::SystemTime;
+
+use crate::crypto::{constant_time_eq, hash_password, needs_rehash, totp, verify_password, Sha256};
+use crate::error::Result;
+use crate::utils::{base32, random};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const USERNAME_MAX_LENGTH: usize = 32;
+
+// Built-in roles. Roles are plain strings, applications can add their own.
+pub mod role {
+    // Full access, including user and session management.
+    pub const ADMIN: &str = "admin";
+    // Can act on internal tools, e.g. revoke sessions.
+    pub const OPERATOR: &str = "operator";
+    // Can look at internal tools but not change anything.
+    pub const READ_ONLY: &str = "read-only";
+}
+
 #[derive(Clone, Debug)]
 pub struct User {
     pub username: String,
-    pub password: String,
+    // Self-describing PBKDF2 hash, see `crypto::password`. Never the plaintext.
+    pub password_hash: String,
+    pub roles: Vec<String>,
+    pub two_factor: Option<TwoFactor>,
+    // Disabled users can't log in or use their API keys.
+    pub disabled: bool,
+}
+
+// Why `username` can't be registered, as a message to show the user. Names
+// end up in URLs and logs, so they are kept to a plain character set.
+pub fn validate_username(username: &str) -> std::result::Result<(), String> {
+    if username.is_empty() || username.len() > USERNAME_MAX_LENGTH {
+        return Err(format!(
+            "Username must be between 1 and {} characters long",
+            USERNAME_MAX_LENGTH
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err("Username may only contain letters, digits, '.', '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+// A TOTP second factor. It only counts once `enabled`, after the user proved
+// their authenticator app works by entering a code from it.
+#[derive(Clone, Debug)]
+pub struct TwoFactor {
+    pub secret: Vec<u8>,
+    pub enabled: bool,
+    // The time step of the last accepted code; it and earlier ones are refused.
+    pub last_used_step: Option<u64>,
+    // SHA-256 hashes of the unused one-time recovery codes.
+    pub recovery_codes: Vec<String>,
+}
+
+impl TwoFactor {
+    pub fn new() -> Result<TwoFactor> {
+        Ok(TwoFactor {
+            secret: totp::generate_secret()?,
+            enabled: false,
+            last_used_step: None,
+            recovery_codes: Vec::new(),
+        })
+    }
+
+    // Accepts a current TOTP code or one of the recovery codes, using either
+    // up so it can't be accepted again.
+    pub fn verify(&mut self, code: &str, now: SystemTime) -> bool {
+        if let Some(step) = totp::verify(&self.secret, code, now, self.last_used_step) {
+            self.last_used_step = Some(step);
+            return true;
+        }
+        let hash = recovery_code_hash(code);
+        let position = self
+            .recovery_codes
+            .iter()
+            .position(|stored| constant_time_eq(stored.as_bytes(), hash.as_bytes()));
+        match position {
+            Some(position) => {
+                self.recovery_codes.remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Replaces the recovery codes, returning the new ones in plain text. This
+    // is the only time they are available.
+    pub fn generate_recovery_codes(&mut self) -> Result<Vec<String>> {
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = base32::encode(&random::bytes(5)?).to_ascii_lowercase();
+            codes.push(format!("{}-{}", &code[..4], &code[4..]));
+        }
+        self.recovery_codes = codes.iter().map(|code| recovery_code_hash(code)).collect();
+        Ok(codes)
+    }
+}
+
+// Codes are compared without case or separators, as people type them.
+fn recovery_code_hash(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    base32::encode(&Sha256::digest(normalized.as_bytes()))
+}
+
+impl User {
+    pub fn new(username: impl ToString, password: &str) -> Result<User> {
+        Ok(User {
+            username: username.to_string(),
+            password_hash: hash_password(password)?,
+            roles: Vec::new(),
+            two_factor: None,
+            disabled: false,
+        })
+    }
+
+    pub fn role(mut self, role: impl ToString) -> Self {
+        let role = role.to_string();
+        if !self.has_role(&role) {
+            self.roles.push(role);
+        }
+        self
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn verify_password(&self, password: &str) -> Result<bool> {
+        verify_password(password, &self.password_hash)
+    }
+
+    pub fn set_password(&mut self, password: &str) -> Result<()> {
+        self.password_hash = hash_password(password)?;
+        Ok(())
+    }
+
+    pub fn password_needs_rehash(&self) -> bool {
+        needs_rehash(&self.password_hash)
+    }
+
+    pub fn two_factor_enabled(&self) -> bool {
+        self.two_factor.as_ref().is_some_and(|two_factor| two_factor.enabled)
+    }
 }
@@ -2,6 +2,7 @@ use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
@@ -19,6 +20,11 @@ impl ThreadPool {
         }
         ThreadPool { workers, sender }
     }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
@@ -26,8 +32,39 @@ impl ThreadPool {
         let job = Box::new(f);
 
         if let Err(e) = self.sender.send(Message::Job(job)) {
-            eprintln!("{}", format!("Error sending message in threadpool {:?}", e));
+            eprintln!("Error sending message in threadpool {:?}", e);
+        }
+    }
+
+    // Lets the workers finish every job already queued, waiting at most
+    // `timeout`. Returns whether they all did; the rest are left running
+    // detached.
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        for _ in &self.workers {
+            if let Err(e) = self.sender.send(Message::Terminate) {
+                eprintln!("Error sending terminate message {:?}", e);
+            }
+        }
+        let deadline = Instant::now() + timeout;
+        while self
+            .workers
+            .iter()
+            .any(|worker| worker.thread.as_ref().is_some_and(|t| !t.is_finished()))
+        {
+            if Instant::now() >= deadline {
+                self.workers.clear();
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        for mut worker in self.workers.drain(..) {
+            if let Some(thread) = worker.thread.take() {
+                if let Err(e) = thread.join() {
+                    eprintln!("Error joining worker thread {} {:?}", worker.id, e);
+                }
+            }
         }
+        true
     }
 }
 
@@ -47,13 +84,13 @@ impl Drop for ThreadPool {
     fn drop(&mut self) {
         for _ in &self.workers {
             if let Err(e) = self.sender.send(Message::Terminate) {
-                eprintln!("{}", format!("Error sending terminate message {:?}", e));
+                eprintln!("Error sending terminate message {:?}", e);
             }
         }
         for worker in &mut self.workers {
             if let Some(thread) = worker.thread.take() {
                 if let Err(e) = thread.join() {
-                    eprintln!("{}", format!("Error joining worker thread {} {:?}",worker.id, e));
+                    eprintln!("Error joining worker thread {} {:?}", worker.id, e);
                 }
             }
         }
@@ -66,7 +103,7 @@ impl Worker {
             let message = receiver.lock().unwrap().recv();
             match message {
                 Ok(Message::Job(job)) => {
-                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job()));
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
                     if let Err(e) = result {
                         let error_message = format!("Job panicked: {:?}", e);
                         eprintln!("{}", &error_message);
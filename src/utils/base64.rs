@@ -0,0 +1,72 @@
+use crate::error::Result;
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// Standard alphabet with `=` padding (RFC 4648, section 4).
+pub fn encode(input: &[u8]) -> String {
+    encode_with(input, STANDARD, true)
+}
+
+pub fn decode(input: &str) -> Result<Vec<u8>> {
+    decode_with(input, STANDARD)
+}
+
+// URL and cookie safe alphabet without padding (RFC 4648, section 5).
+pub fn encode_url(input: &[u8]) -> String {
+    encode_with(input, URL_SAFE, false)
+}
+
+pub fn decode_url(input: &str) -> Result<Vec<u8>> {
+    decode_with(input, URL_SAFE)
+}
+
+fn encode_with(input: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let indices = [
+            b[0] >> 2,
+            ((b[0] & 0x03) << 4) | (b[1] >> 4),
+            ((b[1] & 0x0f) << 2) | (b[2] >> 6),
+            b[2] & 0x3f,
+        ];
+        let chars = chunk.len() + 1;
+        for index in &indices[..chars] {
+            output.push(alphabet[*index as usize] as char);
+        }
+        if pad {
+            output.extend(std::iter::repeat_n('=', 4 - chars));
+        }
+    }
+    output
+}
+
+// Padding is optional when decoding.
+fn decode_with(input: &str, alphabet: &[u8; 64]) -> Result<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in input.bytes() {
+        let value = alphabet
+            .iter()
+            .position(|&c| c == byte)
+            .ok_or("Invalid base64 character")? as u32;
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 6 || buffer != 0 {
+        return Err("Invalid base64 length".into());
+    }
+    Ok(output)
+}
@@ -42,13 +42,12 @@ impl LoggerBackend {
     pub fn new(filename: &str) -> Result<Self, std::io::Error> {
         let parent_dir = std::path::Path::new(filename)
             .parent()
-            .unwrap_or(&std::path::Path::new("."));
+            .unwrap_or(std::path::Path::new("."));
 
-        std::fs::create_dir_all(&parent_dir)?;
+        std::fs::create_dir_all(parent_dir)?;
 
         let file = OpenOptions::new()
             .create(true)
-            .write(true)
             .append(true)
             .open(filename)?;
 
@@ -80,6 +79,15 @@ impl LoggerBackend {
             let _ = file.flush();
         }
     }
+    // Writes out anything still buffered, e.g. before the process exits.
+    pub fn flush(&self) {
+        if let Ok(mut terminal) = self.terminal.lock() {
+            let _ = terminal.flush();
+        }
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
     fn get_timestamp() -> String {
         if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
             let seconds = now.as_secs();
@@ -1,6 +1,15 @@
+pub mod base32;
+pub mod base64;
+pub mod html;
+pub mod http_date;
+pub mod json;
 pub mod logger;
 pub mod logger_backend;
+pub mod random;
 
 
+pub use html::*;
+pub use http_date::*;
+pub use json::Json;
 pub use logger::*;
 pub use logger_backend::*;
@@ -0,0 +1,67 @@
+use std::{fs::File, io::Read};
+
+use crate::error::Result;
+use crate::utils::base64;
+
+// Fills `buffer` from the operating system's CSPRNG. On Linux this is the
+// getrandom syscall, falling back to /dev/urandom on kernels that lack it.
+pub fn fill(buffer: &mut [u8]) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        match getrandom(buffer) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.raw_os_error() == Some(ENOSYS) => {}
+            Err(e) => return Err(format!("getrandom failed: {}", e).into()),
+        }
+    }
+    File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(buffer))
+        .map_err(|e| format!("Unable to read /dev/urandom: {}", e).into())
+}
+
+pub fn bytes(len: usize) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; len];
+    fill(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub fn u64() -> Result<u64> {
+    let mut buffer = [0u8; 8];
+    fill(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+// `len` random bytes as URL-safe base64, usable in cookies, URLs and forms.
+pub fn token(len: usize) -> Result<String> {
+    Ok(base64::encode_url(&bytes(len)?))
+}
+
+#[cfg(target_os = "linux")]
+const ENOSYS: i32 = 38;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    // From the C library std already links against, see getrandom(2).
+    #[link_name = "getrandom"]
+    fn sys_getrandom(buffer: *mut u8, len: usize, flags: u32) -> isize;
+}
+
+#[cfg(target_os = "linux")]
+fn getrandom(buffer: &mut [u8]) -> std::io::Result<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let remaining = &mut buffer[filled..];
+        // SAFETY: the pointer and length describe the unfilled, writable part
+        // of `buffer`, which the kernel writes at most `len` bytes into.
+        let read = unsafe { sys_getrandom(remaining.as_mut_ptr(), remaining.len(), 0) };
+        if read < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        filled += read as usize;
+    }
+    Ok(())
+}